@@ -7,6 +7,12 @@ fn main() {
                     .commands(&["play_async", "get_output_devices", "get_input_devices"])
                     .default_permission(tauri_build::DefaultPermissionRule::AllowAllCommands),
             )
+            .plugin(
+                "caption-file",
+                tauri_build::InlinedPlugin::new()
+                    .commands(&["get_options", "set_options"])
+                    .default_permission(tauri_build::DefaultPermissionRule::AllowAllCommands),
+            )
             .plugin(
                 "custom-tts",
                 tauri_build::InlinedPlugin::new()
@@ -22,7 +28,30 @@ fn main() {
             .plugin(
                 "osc",
                 tauri_build::InlinedPlugin::new()
-                    .commands(&["send"])
+                    .commands(&[
+                        "send",
+                        "send_bundle",
+                        "subscribe",
+                        "unsubscribe",
+                        "set_target",
+                        "remove_target",
+                        "get_targets",
+                        "start_oscquery",
+                        "stop_oscquery",
+                        "discover_vrchat",
+                        "chatbox_submit",
+                        "chatbox_typing",
+                        "chatbox_clear",
+                        "chatbox_configure",
+                        "set_speaking_outputs",
+                        "get_speaking_outputs",
+                        "set_lip_sync",
+                        "get_lip_sync",
+                        "set_avatar_directory",
+                        "get_avatar_directory",
+                        "get_avatar_configs",
+                        "get_active_avatar",
+                    ])
                     .default_permission(tauri_build::DefaultPermissionRule::AllowAllCommands),
             )
             .plugin(
@@ -31,6 +60,12 @@ fn main() {
                     .commands(&["get_voices", "start", "speak", "stop"])
                     .default_permission(tauri_build::DefaultPermissionRule::AllowAllCommands),
             )
+            .plugin(
+                "transcript",
+                tauri_build::InlinedPlugin::new()
+                    .commands(&["list_sessions", "search", "export", "new_session"])
+                    .default_permission(tauri_build::DefaultPermissionRule::AllowAllCommands),
+            )
             .plugin(
                 "translate",
                 tauri_build::InlinedPlugin::new()
@@ -52,13 +87,26 @@ fn main() {
             .plugin(
                 "web",
                 tauri_build::InlinedPlugin::new()
-                    .commands(&["open_browser", "pubsub_broadcast", "config"])
+                    .commands(&[
+                        "open_browser",
+                        "pubsub_broadcast",
+                        "config",
+                        "get_access_options",
+                        "set_access_options",
+                        "regenerate_token",
+                        "get_web_stats",
+                        "get_media_directory",
+                        "set_media_directory",
+                        "discover_servers",
+                        "qr_code",
+                        "get_status",
+                    ])
                     .default_permission(tauri_build::DefaultPermissionRule::AllowAllCommands),
             )
             .plugin(
                 "whisper-stt",
                 tauri_build::InlinedPlugin::new()
-                    .commands(&["start", "stop"])
+                    .commands(&["start", "stop", "set_mute_addresses", "get_muted"])
                     .default_permission(tauri_build::DefaultPermissionRule::AllowAllCommands),
             )
             .plugin(
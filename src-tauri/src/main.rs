@@ -1,10 +1,12 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::sync::Arc;
+
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use tauri::{Manager, State, command};
 use tauri_plugin_window_state::{AppHandleExt, StateFlags};
-use tracing::trace;
+use tracing::{trace, warn};
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 #[cfg(windows)]
 use webview2_com::Microsoft::Web::WebView2::Win32::{
@@ -17,6 +19,7 @@ use webview2_com::Microsoft::Web::WebView2::Win32::{
 use windows::core::{Interface, PCWSTR};
 
 use crate::services::AppConfiguration;
+use crate::services::metrics::Metrics;
 
 mod services;
 
@@ -24,6 +27,10 @@ mod services;
 struct InitArguments {
     #[arg(short, long, default_value_t = 3030)]
     port: u16,
+    /// how many of the following ports to try when `port` is taken, before
+    /// letting the OS pick one
+    #[arg(long, default_value_t = 10)]
+    port_attempts: u16,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -39,7 +46,7 @@ fn get_native_features() -> NativeFeatures {
 }
 
 #[command]
-fn get_port(state: State<'_, InitArguments>) -> u16 {
+fn get_port(state: State<'_, AppConfiguration>) -> u16 {
     state.port
 }
 
@@ -93,6 +100,23 @@ fn app_setup(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// the first free port from `preferred` on, or one the OS assigns when the
+/// following `attempts` ports are taken as well
+fn pick_port(preferred: u16, attempts: u16) -> Option<u16> {
+    let candidates = (0..=attempts)
+        .filter_map(|offset| preferred.checked_add(offset))
+        .chain([0]);
+    for candidate in candidates {
+        // the web server binds the port again once the app is set up, failing
+        // that shows the same error as finding no port here
+        match std::net::TcpListener::bind(("0.0.0.0", candidate)) {
+            Ok(listener) => return listener.local_addr().ok().map(|addr| addr.port()),
+            Err(err) => warn!("could not bind port {candidate}: '{err}'"),
+        }
+    }
+    None
+}
+
 fn main() {
     let filter = EnvFilter::from_default_env();
     #[cfg(debug_assertions)]
@@ -110,28 +134,17 @@ fn main() {
 
     let args = InitArguments::parse();
 
-    // crash if port is not available
-    let port_availability = std::net::TcpListener::bind(format!("0.0.0.0:{}", args.port));
-    match port_availability {
-        Ok(l) => l.set_nonblocking(true).unwrap(),
-        Err(_err) => {
-            #[cfg(windows)]
-            {
-                use windows::Win32::UI::WindowsAndMessaging::{MB_ICONWARNING, MB_OK, MessageBoxA};
-                use windows::core::*;
-                let message = format!("Port {} is not available!", args.port);
-                unsafe {
-                    MessageBoxA(
-                        None,
-                        windows::core::PCSTR(message.as_ptr()),
-                        s!("Curses error"),
-                        MB_OK | MB_ICONWARNING,
-                    );
-                }
-            }
-            return;
-        }
+    let Some(port) = pick_port(args.port, args.port_attempts) else {
+        let message = format!(
+            "No port is available for the web server, tried {} to {} and one assigned by the OS.",
+            args.port,
+            args.port.saturating_add(args.port_attempts)
+        );
+        services::startup_error(&message);
     };
+    if port != args.port {
+        warn!("port {} is not available, using {port} instead", args.port);
+    }
 
     tauri::Builder::default()
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
@@ -140,7 +153,8 @@ fn main() {
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_shell::init())
         .setup(app_setup)
-        .manage(AppConfiguration { port: args.port })
+        .manage(AppConfiguration { port })
+        .manage(Arc::new(Metrics::default()))
         .invoke_handler(tauri::generate_handler![
             get_port,
             get_native_features,
@@ -150,6 +164,8 @@ fn main() {
         .plugin(tauri_plugin_window_state::Builder::default().build())
         .plugin(services::osc::init())
         .plugin(services::web::init())
+        .plugin(services::caption_file::init())
+        .plugin(services::transcript::init())
         .plugin(services::audio::init())
         .plugin(services::windows_tts::init())
         .plugin(services::uberduck_tts::init())
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::{Sample, Source};
+
+/// how often the loudness gets reported per second of audio
+const REPORTS_PER_SECOND: u32 = 20;
+
+/// passes the samples of `inner` through unchanged and reports the loudness
+/// (RMS) of every 1/[`REPORTS_PER_SECOND`] of audio to `report`
+///
+/// samples are pulled right before they are played, so the reports follow
+/// playback closely, `0.` is reported once the source gets dropped
+pub struct Envelope<S, F>
+where
+    F: FnMut(f32),
+{
+    inner: S,
+    report: F,
+    /// samples (across all channels) per report
+    window: usize,
+    count: usize,
+    sum_squares: f32,
+}
+
+impl<S, F> Envelope<S, F>
+where
+    S: Source,
+    S::Item: Sample,
+    F: FnMut(f32),
+{
+    pub fn new(inner: S, report: F) -> Self {
+        let window = (inner.sample_rate() * u32::from(inner.channels()) / REPORTS_PER_SECOND).max(1)
+            as usize;
+        Envelope {
+            inner,
+            report,
+            window,
+            count: 0,
+            sum_squares: 0.,
+        }
+    }
+}
+
+impl<S, F> Iterator for Envelope<S, F>
+where
+    S: Source,
+    S::Item: Sample,
+    F: FnMut(f32),
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.inner.next()?;
+        let value = sample.to_f32();
+        self.sum_squares += value * value;
+        self.count += 1;
+        if self.count >= self.window {
+            (self.report)((self.sum_squares / self.count as f32).sqrt());
+            self.count = 0;
+            self.sum_squares = 0.;
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S, F> Source for Envelope<S, F>
+where
+    S: Source,
+    S::Item: Sample,
+    F: FnMut(f32),
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+impl<S, F> Drop for Envelope<S, F>
+where
+    F: FnMut(f32),
+{
+    fn drop(&mut self) {
+        (self.report)(0.);
+    }
+}
@@ -4,14 +4,19 @@ use std::sync::mpsc::Receiver;
 use std::thread;
 
 use anyhow::{anyhow, bail};
+use envelope::Envelope;
 use rodio::cpal::traits::HostTrait;
 use rodio::cpal::{self};
-use rodio::{Decoder, DeviceTrait, OutputStream, OutputStreamHandle, Sink};
+use rodio::{Decoder, DeviceTrait, OutputStream, OutputStreamHandle, Sample, Sink, Source};
 use serde::{Deserialize, Serialize};
 use tauri::plugin::{Builder, TauriPlugin};
-use tauri::{Runtime, command};
+use tauri::{AppHandle, Manager, Runtime, command};
 use tracing::trace;
 
+use super::osc::OscPlugin;
+
+mod envelope;
+
 fn get_output_stream(device_name: &str) -> Option<(OutputStream, OutputStreamHandle)> {
     let host = cpal::default_host();
     let mut devices = host.output_devices().unwrap();
@@ -33,12 +38,21 @@ pub struct RpcAudioPlayAsync {
 pub struct IndependentSink {
     pub inner: Sink,
     _drop_guard: Receiver<anyhow::Result<Sink>>,
+    /// thread keeping the output stream open
+    thread: thread::JoinHandle<()>,
+}
+
+impl IndependentSink {
+    /// whether the output stream is still open
+    pub fn is_alive(&self) -> bool {
+        !self.thread.is_finished()
+    }
 }
 
 pub fn get_independent_sink(device_name: &str) -> anyhow::Result<IndependentSink> {
     let device_name = device_name.to_string();
     let (tx, rx) = std::sync::mpsc::sync_channel(0);
-    thread::Builder::new()
+    let thread = thread::Builder::new()
         .name("audio".to_string())
         .spawn(move || {
             let Some((_stream, stream_handle)) = get_output_stream(&device_name) else {
@@ -71,11 +85,47 @@ pub fn get_independent_sink(device_name: &str) -> anyhow::Result<IndependentSink
     Ok(IndependentSink {
         inner: sink,
         _drop_guard: rx,
+        thread,
+    })
+}
+
+/// wraps `source` so that its loudness drives the OSC lip sync parameter
+/// while it plays
+pub fn lip_synced<R: Runtime, S>(
+    app: &AppHandle<R>,
+    source: S,
+) -> Envelope<S, impl FnMut(f32) + Send + 'static>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    let app = app.clone();
+    Envelope::new(source, move |level| {
+        app.state::<OscPlugin>().set_mouth_level(level)
     })
 }
 
 #[command]
 pub async fn play_async(data: RpcAudioPlayAsync) -> Result<(), String> {
+    play(data, |source| source)
+}
+
+/// plays like [`play_async`] while streaming the loudness of the audio to the
+/// OSC lip sync parameter
+pub async fn play_async_lip_synced<R: Runtime>(
+    app: &AppHandle<R>,
+    data: RpcAudioPlayAsync,
+) -> Result<(), String> {
+    play(data, |source| lip_synced(app, source))
+}
+
+fn play<S>(
+    data: RpcAudioPlayAsync,
+    wrap: impl FnOnce(Decoder<Cursor<Vec<u8>>>) -> S,
+) -> Result<(), String>
+where
+    S: Source<Item = i16> + Send + 'static,
+{
     if let Some((_stream, stream_handle)) = get_output_stream(data.device_name.as_str()) {
         // let (_stream, stream_handle) = OutputStream::try_default().unwrap();
         let sink = Sink::try_new(&stream_handle).unwrap();
@@ -83,7 +133,7 @@ pub async fn play_async(data: RpcAudioPlayAsync) -> Result<(), String> {
         sink.set_speed(data.rate);
         match Decoder::new(Cursor::new(data.data)) {
             Ok(source) => {
-                sink.append(source);
+                sink.append(wrap(source));
                 sink.sleep_until_end();
                 Ok(())
             }
@@ -0,0 +1,210 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::plugin::{Builder, TauriPlugin};
+use tauri::{Manager, Runtime, State, command};
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+use tracing::warn;
+
+use super::web::{TextEvent, TextEvents, TextSource};
+
+const DEFAULT_THROTTLE_MS: u64 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CaptionFileOptions {
+    /// file showing the caption as it is spoken, interim results included
+    pub interim_path: Option<PathBuf>,
+    /// file showing the latest final captions
+    pub final_path: Option<PathBuf>,
+    /// only captions of this source, any source when `None`
+    pub source: Option<TextSource>,
+    /// final captions kept in the final file, one per line
+    pub max_lines: usize,
+    /// longer captions lose their beginning, unlimited when `None`
+    pub max_chars: Option<usize>,
+    /// files are written at most this often
+    pub throttle_ms: u64,
+}
+
+impl Default for CaptionFileOptions {
+    fn default() -> Self {
+        CaptionFileOptions {
+            interim_path: None,
+            final_path: None,
+            source: None,
+            max_lines: 1,
+            max_chars: None,
+            throttle_ms: DEFAULT_THROTTLE_MS,
+        }
+    }
+}
+
+struct CaptionFile {
+    options: watch::Sender<CaptionFileOptions>,
+}
+
+/// what the files should show
+#[derive(Default, Clone)]
+struct Captions {
+    interim: String,
+    finals: Vec<String>,
+}
+
+impl Captions {
+    fn apply(&mut self, event: TextEvent, options: &CaptionFileOptions) {
+        match event {
+            TextEvent::Text {
+                source,
+                interim,
+                value,
+            } => {
+                if options.source.is_some_and(|wanted| wanted != source) {
+                    return;
+                }
+                let value = truncate(value.trim(), options.max_chars);
+                if !interim && !value.is_empty() {
+                    self.finals.push(value.clone());
+                    let excess = self.finals.len().saturating_sub(options.max_lines.max(1));
+                    self.finals.drain(..excess);
+                }
+                // the final result replaces the interim ones it concludes
+                self.interim = value;
+            }
+            TextEvent::Clear => *self = Captions::default(),
+        }
+    }
+
+    fn write(&self, options: &CaptionFileOptions) {
+        if let Some(path) = &options.interim_path {
+            write_atomic(path, &self.interim);
+        }
+        if let Some(path) = &options.final_path {
+            write_atomic(path, &self.finals.join("\n"));
+        }
+    }
+}
+
+/// the end of `value`, cut at a word boundary where possible
+fn truncate(value: &str, max_chars: Option<usize>) -> String {
+    let Some(max_chars) = max_chars else {
+        return value.to_string();
+    };
+    let len = value.chars().count();
+    if len <= max_chars {
+        return value.to_string();
+    }
+    let tail: String = value.chars().skip(len - max_chars).collect();
+    match tail.split_once(char::is_whitespace) {
+        Some((_, rest)) if !rest.trim().is_empty() => rest.trim_start().to_string(),
+        _ => tail,
+    }
+}
+
+/// writes a sibling file and renames it over `path`, so text sources never
+/// read a half written caption
+fn write_atomic(path: &Path, text: &str) {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let result = std::fs::File::create(&temporary)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .and_then(|_| std::fs::rename(&temporary, path));
+    if let Err(err) = result {
+        warn!("could not write caption file '{}': '{err}'", path.display());
+    }
+}
+
+/// applies text events as they arrive and writes the files at most every
+/// [`CaptionFileOptions::throttle_ms`]
+async fn run(
+    mut events: broadcast::Receiver<TextEvent>,
+    mut options: watch::Receiver<CaptionFileOptions>,
+) {
+    let mut captions = Captions::default();
+    // the next write once something changed
+    let mut pending: Option<Instant> = None;
+    let mut last_write = Instant::now();
+    loop {
+        let deadline = pending;
+        let write_at = async move {
+            match deadline {
+                Some(at) => tokio::time::sleep_until(at).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            event = events.recv() => {
+                match event {
+                    Ok(event) => captions.apply(event, &options.borrow()),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("caption file skipped {skipped} text events");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+                let throttle = Duration::from_millis(options.borrow().throttle_ms);
+                pending.get_or_insert(last_write + throttle);
+            }
+            changed = options.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                pending.get_or_insert(Instant::now());
+            }
+            _ = write_at => {
+                let options = options.borrow().clone();
+                let snapshot = captions.clone();
+                let written =
+                    tauri::async_runtime::spawn_blocking(move || snapshot.write(&options)).await;
+                if let Err(err) = written {
+                    warn!("could not write caption files: '{err}'");
+                }
+                pending = None;
+                last_write = Instant::now();
+            }
+        }
+    }
+}
+
+#[command]
+fn get_options(state: State<'_, CaptionFile>) -> CaptionFileOptions {
+    state.options.borrow().clone()
+}
+
+/// changes where and how captions are written, no paths stops writing
+#[command]
+fn set_options(options: CaptionFileOptions, state: State<'_, CaptionFile>) -> Result<(), String> {
+    for path in [&options.interim_path, &options.final_path]
+        .into_iter()
+        .flatten()
+    {
+        let directory = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty());
+        if directory.is_some_and(|directory| !directory.is_dir()) {
+            return Err(format!(
+                "'{}' is not in an existing directory",
+                path.display()
+            ));
+        }
+    }
+    state.options.send_replace(options);
+    Ok(())
+}
+
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new("caption-file")
+        .invoke_handler(tauri::generate_handler![get_options, set_options])
+        .setup(|app, _api| {
+            let (options, options_rx) = watch::channel(CaptionFileOptions::default());
+            let events = app.state::<TextEvents>().subscribe();
+            tauri::async_runtime::spawn(run(events, options_rx));
+            app.manage(CaptionFile { options });
+            Ok(())
+        })
+        .build()
+}
@@ -2,7 +2,7 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{Context, anyhow};
 use serde::{Deserialize, Serialize};
-use tauri::{Runtime, plugin};
+use tauri::{AppHandle, Runtime, plugin};
 use tokio::process::Command;
 
 /// arguments to the `speak` function. most of these get passed straight to the
@@ -112,8 +112,8 @@ fn build_command(script: &Path, txtfile: &Path, outfile: &Path) -> Command {
 }
 
 #[tauri::command]
-async fn speak(args: SpeakArgs) -> Result<(), String> {
-    use crate::services::audio::{RpcAudioPlayAsync, play_async};
+async fn speak<R: Runtime>(app: AppHandle<R>, args: SpeakArgs) -> Result<(), String> {
+    use crate::services::audio::{RpcAudioPlayAsync, play_async_lip_synced};
 
     // fast path for empty string
     if args.value.is_empty() {
@@ -134,7 +134,7 @@ async fn speak(args: SpeakArgs) -> Result<(), String> {
         rate: 1.0,
     };
 
-    play_async(play_async_args).await
+    play_async_lip_synced(&app, play_async_args).await
 }
 
 pub fn init<R: Runtime>() -> plugin::TauriPlugin<R> {
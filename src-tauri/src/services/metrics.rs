@@ -0,0 +1,187 @@
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// upper bounds of the buckets for durations, in seconds
+const SECONDS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10., 30.];
+/// upper bounds of the buckets for the whisper real time factor, below 1 is
+/// faster than real time
+const FACTORS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 0.75, 1., 1.5, 2., 5.];
+/// upper bounds of the buckets for how many clients got a message
+const RECIPIENTS: &[f64] = &[0., 1., 2., 4., 8., 16., 32., 64.];
+
+/// monotonically increasing count
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    fn new(name: &'static str, help: &'static str) -> Self {
+        Counter {
+            name,
+            help,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn add(&self, value: u64) {
+        self.value.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "# HELP {} {}", self.name, self.help)?;
+        writeln!(out, "# TYPE {} counter", self.name)?;
+        writeln!(out, "{} {}", self.name, self.value.load(Ordering::Relaxed))
+    }
+}
+
+struct Observations {
+    /// per bucket, not cumulative
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// distribution of observed values over fixed buckets
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    bounds: &'static [f64],
+    observations: Mutex<Observations>,
+}
+
+impl Histogram {
+    fn new(name: &'static str, help: &'static str, bounds: &'static [f64]) -> Self {
+        Histogram {
+            name,
+            help,
+            bounds,
+            observations: Mutex::new(Observations {
+                buckets: vec![0; bounds.len()],
+                sum: 0.,
+                count: 0,
+            }),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let mut observations = self
+            .observations
+            .lock()
+            .expect("should be able to lock histogram");
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            observations.buckets[bucket] += 1;
+        }
+        observations.sum += value;
+        observations.count += 1;
+    }
+
+    pub fn observe_duration(&self, duration: Duration) {
+        self.observe(duration.as_secs_f64());
+    }
+
+    fn render(&self, out: &mut String) -> std::fmt::Result {
+        let observations = self
+            .observations
+            .lock()
+            .expect("should be able to lock histogram");
+        writeln!(out, "# HELP {} {}", self.name, self.help)?;
+        writeln!(out, "# TYPE {} histogram", self.name)?;
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&observations.buckets) {
+            cumulative += count;
+            writeln!(out, "{}_bucket{{le=\"{bound}\"}} {cumulative}", self.name)?;
+        }
+        writeln!(
+            out,
+            "{}_bucket{{le=\"+Inf\"}} {}",
+            self.name, observations.count
+        )?;
+        writeln!(out, "{}_sum {}", self.name, observations.sum)?;
+        writeln!(out, "{}_count {}", self.name, observations.count)
+    }
+}
+
+/// numbers on the speech pipeline, served by the web plugin as `/metrics` in
+/// the Prometheus text format
+pub struct Metrics {
+    /// length of the speech segments the VAD cut, its count is the number of
+    /// segments
+    pub vad_segment_seconds: Histogram,
+    /// time whisper took to transcribe a segment
+    pub transcription_seconds: Histogram,
+    /// time from the end of a segment to its transcription, including the
+    /// wait behind earlier segments
+    pub final_latency_seconds: Histogram,
+    /// transcription time divided by segment length
+    pub whisper_real_time_factor: Histogram,
+    /// microphone frames dropped because processing could not keep up
+    pub dropped_audio_frames: Counter,
+    /// time piper took to synthesize a line
+    pub piper_synthesis_seconds: Histogram,
+    /// clients a pubsub message was sent to
+    pub pubsub_fanout: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            vad_segment_seconds: Histogram::new(
+                "curses_vad_segment_duration_seconds",
+                "Length of the speech segments detected by the VAD.",
+                SECONDS,
+            ),
+            transcription_seconds: Histogram::new(
+                "curses_whisper_transcription_seconds",
+                "Time whisper took to transcribe one speech segment.",
+                SECONDS,
+            ),
+            final_latency_seconds: Histogram::new(
+                "curses_whisper_final_latency_seconds",
+                "Time from the end of a speech segment to its whisper result, queueing included.",
+                SECONDS,
+            ),
+            whisper_real_time_factor: Histogram::new(
+                "curses_whisper_real_time_factor",
+                "Whisper transcription time divided by the segment length.",
+                FACTORS,
+            ),
+            dropped_audio_frames: Counter::new(
+                "curses_dropped_audio_frames_total",
+                "Microphone frames dropped because processing could not keep up.",
+            ),
+            piper_synthesis_seconds: Histogram::new(
+                "curses_piper_synthesis_seconds",
+                "Time piper took to synthesize one line.",
+                SECONDS,
+            ),
+            pubsub_fanout: Histogram::new(
+                "curses_pubsub_fanout_clients",
+                "Number of websocket clients a pubsub message was sent to.",
+                RECIPIENTS,
+            ),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out)
+            .expect("writing to a string should not fail");
+        out
+    }
+
+    fn write(&self, out: &mut String) -> std::fmt::Result {
+        self.vad_segment_seconds.render(out)?;
+        self.transcription_seconds.render(out)?;
+        self.final_latency_seconds.render(out)?;
+        self.whisper_real_time_factor.render(out)?;
+        self.dropped_audio_frames.render(out)?;
+        self.piper_synthesis_seconds.render(out)?;
+        self.pubsub_fanout.render(out)
+    }
+}
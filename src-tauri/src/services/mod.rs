@@ -1,8 +1,11 @@
 pub mod audio;
+pub mod caption_file;
 pub mod custom_tts;
 pub mod keyboard;
+pub mod metrics;
 pub mod osc;
 pub mod piper_tts;
+pub mod transcript;
 pub mod uberduck_tts;
 pub mod uwu;
 pub mod web;
@@ -13,3 +16,18 @@ pub mod windows_tts;
 pub struct AppConfiguration {
     pub port: u16,
 }
+
+/// logs `message` and shows it in a dialog before exiting, for errors that
+/// leave the app unusable while it starts
+///
+/// has to run on the main thread
+pub fn startup_error(message: &str) -> ! {
+    tracing::error!("{message}");
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title("Curses error")
+        .set_description(message)
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+    std::process::exit(1);
+}
@@ -0,0 +1,183 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use rosc::{OscMessage, OscType};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+use super::{OscError, OscPlugin};
+
+/// VRChat announces the id of a newly loaded avatar on this address
+pub const AVATAR_CHANGE_ADDRESS: &str = "/avatar/change";
+
+/// where VRChat keeps its OSC configs, relative to the home directory
+#[cfg(windows)]
+const CONFIG_DIRECTORY: &str = r"AppData\LocalLow\VRChat\VRChat\OSC";
+/// where VRChat keeps its OSC configs when running through Proton, relative
+/// to the home directory
+#[cfg(not(windows))]
+const CONFIG_DIRECTORY: &str = ".steam/steam/steamapps/compatdata/438100/pfx/drive_c/users/steamuser/AppData/LocalLow/VRChat/VRChat/OSC";
+
+/// per avatar OSC config as written by VRChat to
+/// `OSC/usr_<user id>/Avatars/<avatar id>.json`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AvatarConfig {
+    pub id: String,
+    pub name: String,
+    pub parameters: Vec<AvatarParameter>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AvatarParameter {
+    pub name: String,
+    /// where the parameter can be set, missing for read only parameters
+    #[serde(default)]
+    pub input: Option<ParameterEndpoint>,
+    /// where changes of the parameter get reported
+    #[serde(default)]
+    pub output: Option<ParameterEndpoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParameterEndpoint {
+    pub address: String,
+    #[serde(rename = "type")]
+    pub kind: ParameterType,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterType {
+    Bool,
+    Int,
+    Float,
+}
+
+/// payload of the `osc_avatar_change` event
+#[derive(Serialize, Clone, Debug)]
+struct AvatarChange {
+    id: String,
+    /// `None` when no config could be found for the avatar
+    config: Option<AvatarConfig>,
+}
+
+/// the config directory of a VRChat install in its default location
+pub fn default_directory(home: &Path) -> PathBuf {
+    home.join(CONFIG_DIRECTORY)
+}
+
+/// parses the configs of all avatars of all users found in `directory`
+pub async fn read_all(directory: &Path) -> Result<Vec<AvatarConfig>, OscError> {
+    let mut configs = Vec::new();
+    for path in config_files(directory).await? {
+        match read(&path).await {
+            Ok(config) => configs.push(config),
+            Err(err) => warn!("skipping avatar config: {err}"),
+        }
+    }
+    Ok(configs)
+}
+
+/// parses the config of avatar `id`, the most recently written one wins when
+/// several users have a config for it
+pub async fn read_by_id(directory: &Path, id: &str) -> Result<Option<AvatarConfig>, OscError> {
+    let file_name = format!("{id}.json");
+    let mut newest = None;
+    for path in config_files(directory).await? {
+        if path
+            .file_name()
+            .is_none_or(|name| name != file_name.as_str())
+        {
+            continue;
+        }
+        let modified = tokio::fs::metadata(&path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if newest.as_ref().is_none_or(|(newest, _)| modified > *newest) {
+            newest = Some((modified, path));
+        }
+    }
+    match newest {
+        Some((_, path)) => read(&path).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+async fn read(path: &Path) -> Result<AvatarConfig, OscError> {
+    let content = tokio::fs::read(path)
+        .await
+        .map_err(|err| OscError::AvatarConfig(path.to_path_buf(), err.to_string()))?;
+    // VRChat writes the files with a byte order mark
+    let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&content);
+    serde_json::from_slice(content)
+        .map_err(|err| OscError::AvatarConfig(path.to_path_buf(), err.to_string()))
+}
+
+/// every `<directory>/<user>/Avatars/*.json`
+async fn config_files(directory: &Path) -> Result<Vec<PathBuf>, OscError> {
+    let mut users = tokio::fs::read_dir(directory)
+        .await
+        .map_err(|err| OscError::AvatarConfig(directory.to_path_buf(), err.to_string()))?;
+    let mut files = Vec::new();
+    while let Ok(Some(user)) = users.next_entry().await {
+        let avatars = user.path().join("Avatars");
+        let mut entries = match tokio::fs::read_dir(&avatars).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => {
+                warn!("could not list '{}': '{err}'", avatars.display());
+                continue;
+            }
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// follows `/avatar/change` in the `inbound` OSC messages, remembers the
+/// active avatar and emits it with its config as `osc_avatar_change` event
+pub async fn watch<R: Runtime>(app: AppHandle<R>, mut inbound: broadcast::Receiver<OscMessage>) {
+    loop {
+        let message = match inbound.recv().await {
+            Ok(message) => message,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("avatar watcher fell behind, skipped {skipped} osc messages");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if message.addr != AVATAR_CHANGE_ADDRESS {
+            continue;
+        }
+        let Some(OscType::String(id)) = message.args.into_iter().next() else {
+            continue;
+        };
+        debug!("avatar changed to '{id}'");
+        let state = app.state::<OscPlugin>();
+        state.set_active_avatar(id.clone());
+        let config = match state.avatar_config(&app, &id).await {
+            Ok(config) => config,
+            Err(err) => {
+                warn!("could not load config of avatar '{id}': '{err}'");
+                None
+            }
+        };
+        if app
+            .emit("osc_avatar_change", AvatarChange { id, config })
+            .is_err()
+        {
+            warn!("wasn't able to emit avatar change to frontend");
+        }
+    }
+}
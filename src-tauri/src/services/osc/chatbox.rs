@@ -0,0 +1,267 @@
+use std::mem;
+use std::time::Duration;
+
+use rosc::{OscMessage, OscType};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, sleep_until};
+use tracing::warn;
+
+use super::OscPlugin;
+
+/// VRChat truncates chatbox messages after this many characters
+pub const CHATBOX_LIMIT: usize = 144;
+/// VRChat drops chatbox messages that arrive faster than this
+const MIN_INPUT_INTERVAL: Duration = Duration::from_millis(1500);
+/// how often the typing indicator gets refreshed while it stays on
+const TYPING_INTERVAL: Duration = Duration::from_millis(1400);
+
+const INPUT_ADDRESS: &str = "/chatbox/input";
+const TYPING_ADDRESS: &str = "/chatbox/typing";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ChatboxOptions {
+    /// how long each page stays up before the next one replaces it
+    pub page_interval_ms: u64,
+    /// whether VRChat should play the notification sound for new messages
+    pub notify: bool,
+    /// names of the targets to send to, empty sends to the default target
+    pub targets: Vec<String>,
+}
+
+impl Default for ChatboxOptions {
+    fn default() -> Self {
+        ChatboxOptions {
+            page_interval_ms: 3000,
+            notify: false,
+            targets: Vec::new(),
+        }
+    }
+}
+
+pub enum ChatboxCommand {
+    /// replaces whatever is queued with `text`
+    Submit(String),
+    Typing(bool),
+    Clear,
+    Configure(ChatboxOptions),
+}
+
+/// payload of the `osc_chatbox` event
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum ChatboxEvent {
+    /// `page` (1 based) of `pages` was sent
+    Page {
+        page: usize,
+        pages: usize,
+        text: String,
+    },
+    /// nothing left to send
+    Idle,
+}
+
+#[derive(Default)]
+struct Chatbox {
+    options: ChatboxOptions,
+    pages: Vec<String>,
+    next_page: usize,
+    next_page_at: Option<Instant>,
+    last_input: Option<Instant>,
+    typing: bool,
+    last_typing: Option<Instant>,
+}
+
+/// Owns the VRChat chatbox, pages long text and keeps to VRChat's rate limit
+pub async fn run<R: Runtime>(
+    app: AppHandle<R>,
+    mut commands: mpsc::UnboundedReceiver<ChatboxCommand>,
+) {
+    let mut chatbox = Chatbox::default();
+    loop {
+        let due = chatbox.next_page_at;
+        select! {
+            command = commands.recv() => {
+                match command {
+                    Some(command) => chatbox.handle(&app, command),
+                    None => return,
+                }
+            },
+            _ = sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                chatbox.show_next_page(&app);
+            }
+        }
+    }
+}
+
+impl Chatbox {
+    fn handle<R: Runtime>(&mut self, app: &AppHandle<R>, command: ChatboxCommand) {
+        match command {
+            ChatboxCommand::Submit(text) => {
+                self.pages = paginate(&text);
+                self.next_page = 0;
+                if self.pages.is_empty() {
+                    self.next_page_at = None;
+                    emit(app, ChatboxEvent::Idle);
+                    return;
+                }
+                let earliest = self
+                    .last_input
+                    .map(|last| last + MIN_INPUT_INTERVAL)
+                    .unwrap_or_else(Instant::now);
+                self.next_page_at = Some(earliest.max(Instant::now()));
+            }
+            ChatboxCommand::Typing(typing) => {
+                let recently_sent = self
+                    .last_typing
+                    .is_some_and(|last| last.elapsed() < TYPING_INTERVAL);
+                if typing == self.typing && recently_sent {
+                    return;
+                }
+                self.send(app, TYPING_ADDRESS, vec![OscType::Bool(typing)]);
+                self.typing = typing;
+                self.last_typing = Some(Instant::now());
+            }
+            ChatboxCommand::Clear => {
+                self.pages.clear();
+                self.next_page_at = None;
+                self.send_input(app, String::new());
+                emit(app, ChatboxEvent::Idle);
+            }
+            ChatboxCommand::Configure(options) => self.options = options,
+        }
+    }
+
+    fn show_next_page<R: Runtime>(&mut self, app: &AppHandle<R>) {
+        let Some(text) = self.pages.get(self.next_page).cloned() else {
+            self.next_page_at = None;
+            return;
+        };
+        self.send_input(app, text.clone());
+        self.next_page += 1;
+        emit(
+            app,
+            ChatboxEvent::Page {
+                page: self.next_page,
+                pages: self.pages.len(),
+                text,
+            },
+        );
+
+        if self.next_page < self.pages.len() {
+            let interval = Duration::from_millis(self.options.page_interval_ms);
+            self.next_page_at = Some(Instant::now() + interval.max(MIN_INPUT_INTERVAL));
+        } else {
+            self.next_page_at = None;
+            emit(app, ChatboxEvent::Idle);
+        }
+    }
+
+    fn send_input<R: Runtime>(&mut self, app: &AppHandle<R>, text: String) {
+        self.send(
+            app,
+            INPUT_ADDRESS,
+            vec![
+                OscType::String(text),
+                OscType::Bool(true), // send directly instead of opening the keyboard
+                OscType::Bool(self.options.notify),
+            ],
+        );
+        self.last_input = Some(Instant::now());
+        // VRChat turns the indicator off when a message arrives
+        self.typing = false;
+    }
+
+    fn send<R: Runtime>(&self, app: &AppHandle<R>, address: &str, args: Vec<OscType>) {
+        let message = OscMessage {
+            addr: address.to_string(),
+            args,
+        };
+        if let Err(err) = app
+            .state::<OscPlugin>()
+            .send_message(message, &self.options.targets)
+        {
+            warn!("could not update chatbox: '{err}'");
+        }
+    }
+}
+
+fn emit<R: Runtime>(app: &AppHandle<R>, event: ChatboxEvent) {
+    if app.emit("osc_chatbox", event).is_err() {
+        warn!("wasn't able to emit chatbox progress to frontend");
+    }
+}
+
+/// splits `text` into chatbox sized pages at word boundaries, when more than
+/// one page is needed each one ends in a ` (page/pages)` marker
+pub fn paginate(text: &str) -> Vec<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let single = words.join(" ");
+    if single.chars().count() <= CHATBOX_LIMIT {
+        return vec![single];
+    }
+
+    // the marker takes up more room once the page count gains a digit
+    let mut assumed_pages: usize = 2;
+    loop {
+        let marker_len = format!(" ({assumed_pages}/{assumed_pages})").len();
+        let pages = wrap(&words, CHATBOX_LIMIT - marker_len);
+        if pages.len().to_string().len() > assumed_pages.to_string().len() {
+            assumed_pages = pages.len();
+            continue;
+        }
+        let count = pages.len();
+        return pages
+            .into_iter()
+            .enumerate()
+            .map(|(i, page)| format!("{page} ({}/{count})", i + 1))
+            .collect();
+    }
+}
+
+/// greedily fills lines of at most `width` characters, words longer than a
+/// whole line get split
+fn wrap(words: &[&str], width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_len = 0;
+    for word in words {
+        let mut word: &str = word;
+        while !word.is_empty() {
+            let word_len = word.chars().count();
+            let needed = match line_len {
+                0 => word_len,
+                len => len + 1 + word_len,
+            };
+            if needed <= width {
+                if line_len != 0 {
+                    line.push(' ');
+                }
+                line.push_str(word);
+                line_len = needed;
+                break;
+            }
+            if line_len != 0 {
+                lines.push(mem::take(&mut line));
+                line_len = 0;
+                continue;
+            }
+            let split = word
+                .char_indices()
+                .nth(width)
+                .map_or(word.len(), |(i, _)| i);
+            lines.push(word[..split].to_string());
+            word = &word[split..];
+        }
+    }
+    if line_len != 0 {
+        lines.push(line);
+    }
+    lines
+}
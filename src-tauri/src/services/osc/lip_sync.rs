@@ -0,0 +1,33 @@
+use rosc::{OscMessage, OscType};
+use serde::{Deserialize, Serialize};
+
+/// how TTS playback loudness is turned into a float avatar parameter
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LipSyncOptions {
+    /// address receiving the mouth openness in `0..=1`
+    pub path: String,
+    /// factor applied to the RMS loudness, speech rarely gets above ~0.3
+    pub gain: f32,
+    /// names of the targets to send to, empty sends to the default target
+    pub targets: Vec<String>,
+}
+
+impl Default for LipSyncOptions {
+    fn default() -> Self {
+        LipSyncOptions {
+            path: "/avatar/parameters/TTS_Mouth".to_string(),
+            gain: 3.,
+            targets: Vec::new(),
+        }
+    }
+}
+
+impl LipSyncOptions {
+    pub fn message(&self, level: f32) -> OscMessage {
+        OscMessage {
+            addr: self.path.clone(),
+            args: vec![OscType::Float((level * self.gain).clamp(0., 1.))],
+        }
+    }
+}
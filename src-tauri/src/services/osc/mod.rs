@@ -0,0 +1,632 @@
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use avatar::AvatarConfig;
+use chatbox::{ChatboxCommand, ChatboxOptions};
+use lip_sync::LipSyncOptions;
+use oscquery::{OscQueryHost, OscQueryInfo};
+use rosc::address::Matcher;
+use rosc::{OscBundle, OscMessage, OscPacket, OscType, encoder};
+use serde::{Deserialize, Serialize};
+use speaking::SpeakingOutput;
+use targets::{DEFAULT_TARGET, Destination, OscTarget};
+use tauri::plugin::{Builder, TauriPlugin};
+use tauri::{AppHandle, Manager, Runtime, State, command};
+use thiserror::Error;
+use tokio::sync::{broadcast, mpsc};
+use tracing::warn;
+use value::{OscValue, Timetag};
+
+mod avatar;
+mod chatbox;
+mod lip_sync;
+mod oscquery;
+mod receive;
+mod speaking;
+mod targets;
+mod value;
+
+/// address the plugin socket tries to bind to, both for sending and receiving
+const DEFAULT_BIND: &str = "127.0.0.1:3400";
+const DEFAULT_DISCOVERY_TIMEOUT_MS: u64 = 5000;
+/// inbound messages native listeners may fall behind on before skipping some
+const INBOUND_CAPACITY: usize = 256;
+
+pub type SubscriptionId = u32;
+
+#[derive(Error, Debug)]
+pub enum OscError {
+    #[error("unknown osc target '{0}'")]
+    UnknownTarget(String),
+    #[error("could not resolve osc target '{0}'")]
+    Resolve(String),
+    #[error("could not bind osc socket to '{0}': '{1}'")]
+    Bind(String, std::io::Error),
+    #[error("invalid osc address pattern '{0}': '{1}'")]
+    InvalidPattern(String, rosc::OscError),
+    #[error("osc argument out of range: {0}")]
+    OutOfRange(String),
+    #[error("could not encode osc packet: '{0}'")]
+    Encode(rosc::OscError),
+    #[error("could not send osc packet to {0}: '{1}'")]
+    Send(SocketAddr, std::io::Error),
+    #[error("oscquery error: '{0}'")]
+    OscQuery(String),
+    #[error("could not locate the VRChat OSC config directory")]
+    NoAvatarDirectory,
+    #[error("could not read avatar config '{0}': '{1}'")]
+    AvatarConfig(PathBuf, String),
+}
+
+impl Serialize for OscError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+struct Subscription {
+    pattern: String,
+    matcher: Matcher,
+}
+
+#[derive(Default)]
+struct Subscriptions {
+    next_id: SubscriptionId,
+    patterns: HashMap<SubscriptionId, Subscription>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct OscStatus {
+    /// address the plugin socket is bound to
+    pub address: Option<SocketAddr>,
+    /// whether the socket had to fall back to a random port because
+    /// [`DEFAULT_BIND`] was taken
+    pub fallback: bool,
+    /// whether inbound messages are still being read
+    pub receiving: bool,
+    pub oscquery: bool,
+}
+
+impl OscStatus {
+    pub fn healthy(&self) -> bool {
+        self.address.is_some() && self.receiving
+    }
+}
+
+pub struct OscPlugin {
+    /// socket bound to [`DEFAULT_BIND`] (or a random port when that is taken),
+    /// inbound messages are read from it
+    socket: Arc<UdpSocket>,
+    /// thread reading inbound messages from the socket, see [`receive`]
+    receiver: OnceLock<JoinHandle<()>>,
+    targets: RwLock<HashMap<String, Destination>>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    oscquery: Mutex<Option<OscQueryHost>>,
+    chatbox: mpsc::UnboundedSender<ChatboxCommand>,
+    inbound: broadcast::Sender<OscMessage>,
+    speaking_outputs: RwLock<Vec<SpeakingOutput>>,
+    /// TTS lip sync is disabled while this is `None`
+    lip_sync: RwLock<Option<LipSyncOptions>>,
+    /// VRChat OSC config directory, the default location when `None`
+    avatar_directory: RwLock<Option<PathBuf>>,
+    /// id of the avatar last announced through `/avatar/change`
+    active_avatar: RwLock<Option<String>>,
+}
+
+impl OscPlugin {
+    fn bind(chatbox: mpsc::UnboundedSender<ChatboxCommand>) -> std::io::Result<Self> {
+        let socket = match UdpSocket::bind(DEFAULT_BIND) {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!("could not bind osc socket to {DEFAULT_BIND} ('{err}'), using a random port");
+                UdpSocket::bind("127.0.0.1:0")?
+            }
+        };
+        let socket = Arc::new(socket);
+        let targets = HashMap::from([(
+            DEFAULT_TARGET.to_string(),
+            Destination::default_target(&socket),
+        )]);
+        Ok(OscPlugin {
+            socket,
+            receiver: OnceLock::new(),
+            targets: RwLock::new(targets),
+            subscriptions: Arc::default(),
+            oscquery: Mutex::default(),
+            chatbox,
+            inbound: broadcast::channel(INBOUND_CAPACITY).0,
+            speaking_outputs: RwLock::default(),
+            lip_sync: RwLock::default(),
+            avatar_directory: RwLock::default(),
+            active_avatar: RwLock::default(),
+        })
+    }
+
+    /// receives every inbound message regardless of subscriptions, for native
+    /// consumers that must not wait for a round trip through the frontend
+    pub fn listen(&self) -> broadcast::Receiver<OscMessage> {
+        self.inbound.subscribe()
+    }
+
+    /// reports that speech started or ended to every configured
+    /// [`SpeakingOutput`]
+    pub fn set_speaking(&self, speaking: bool) {
+        for output in self
+            .speaking_outputs
+            .read()
+            .expect("should be able to lock speaking outputs")
+            .iter()
+        {
+            output.apply(self, speaking);
+        }
+    }
+
+    pub fn status(&self) -> OscStatus {
+        let address = self.socket.local_addr().ok();
+        OscStatus {
+            address,
+            fallback: address.is_some_and(|address| address.to_string() != DEFAULT_BIND),
+            receiving: self
+                .receiver
+                .get()
+                .is_some_and(|receiver| !receiver.is_finished()),
+            oscquery: self
+                .oscquery
+                .lock()
+                .expect("should be able to lock oscquery")
+                .is_some(),
+        }
+    }
+
+    /// sends the loudness `level` of the currently playing TTS audio to the
+    /// lip sync parameter, if enabled
+    pub fn set_mouth_level(&self, level: f32) {
+        let lip_sync = self
+            .lip_sync
+            .read()
+            .expect("should be able to lock lip sync");
+        let Some(options) = lip_sync.as_ref() else {
+            return;
+        };
+        if let Err(err) = self.send_message(options.message(level), &options.targets) {
+            warn!("could not send lip sync parameter: '{err}'");
+        }
+    }
+
+    fn avatar_directory<R: Runtime>(&self, app: &AppHandle<R>) -> Result<PathBuf, OscError> {
+        let configured = self
+            .avatar_directory
+            .read()
+            .expect("should be able to lock avatar directory")
+            .clone();
+        match configured {
+            Some(directory) => Ok(directory),
+            None => app
+                .path()
+                .home_dir()
+                .map(|home| avatar::default_directory(&home))
+                .map_err(|_| OscError::NoAvatarDirectory),
+        }
+    }
+
+    fn set_active_avatar(&self, id: String) {
+        *self
+            .active_avatar
+            .write()
+            .expect("should be able to lock active avatar") = Some(id);
+    }
+
+    async fn avatar_config<R: Runtime>(
+        &self,
+        app: &AppHandle<R>,
+        id: &str,
+    ) -> Result<Option<AvatarConfig>, OscError> {
+        let directory = self.avatar_directory(app)?;
+        avatar::read_by_id(&directory, id).await
+    }
+
+    /// looks up where messages selecting `names` need to go, an empty
+    /// selection means [`DEFAULT_TARGET`]
+    fn destinations(
+        &self,
+        names: &[String],
+    ) -> Result<Vec<(SocketAddr, Arc<UdpSocket>)>, OscError> {
+        let targets = self.targets.read().expect("should be able to lock targets");
+        let lookup = |name: &str| {
+            targets
+                .get(name)
+                .map(|target| (target.addr, target.socket.clone()))
+                .ok_or_else(|| OscError::UnknownTarget(name.to_string()))
+        };
+        if names.is_empty() {
+            return Ok(vec![lookup(DEFAULT_TARGET)?]);
+        }
+        names.iter().map(|name| lookup(name)).collect()
+    }
+
+    fn send(&self, rpc: RpcOscMessage) -> Result<(), OscError> {
+        let message = encode_message(rpc.path, rpc.args)?;
+        self.send_packet(&OscPacket::Message(message), &rpc.targets)
+    }
+
+    fn send_bundle(&self, rpc: RpcOscBundle) -> Result<(), OscError> {
+        let timetag = match (rpc.timetag, rpc.delay_ms) {
+            (Some(timetag), _) => timetag,
+            (None, Some(delay)) => Timetag::after(Duration::from_millis(delay))?,
+            (None, None) => Timetag::IMMEDIATELY,
+        };
+        let content = rpc
+            .messages
+            .into_iter()
+            .map(|message| encode_message(message.path, message.args).map(OscPacket::Message))
+            .collect::<Result<_, _>>()?;
+        let bundle = OscBundle {
+            timetag: timetag.into(),
+            content,
+        };
+        self.send_packet(&OscPacket::Bundle(bundle), &rpc.targets)
+    }
+
+    fn send_message(&self, message: OscMessage, targets: &[String]) -> Result<(), OscError> {
+        self.send_packet(&OscPacket::Message(message), targets)
+    }
+
+    /// sends `packet` to every selected target, a failing target does not keep
+    /// the packet from the remaining ones
+    fn send_packet(&self, packet: &OscPacket, targets: &[String]) -> Result<(), OscError> {
+        let destinations = self.destinations(targets)?;
+        let buf = encoder::encode(packet).map_err(OscError::Encode)?;
+        let mut result = Ok(());
+        for (addr, socket) in destinations {
+            if let Err(err) = socket.send_to(&buf, addr) {
+                warn!("could not send osc packet to {addr}: '{err}'");
+                if result.is_ok() {
+                    result = Err(OscError::Send(addr, err));
+                }
+            }
+        }
+        result
+    }
+
+    fn chatbox(&self, command: ChatboxCommand) {
+        if self.chatbox.send(command).is_err() {
+            warn!("chatbox scheduler is not running");
+        }
+    }
+
+    fn subscribe(&self, pattern: String) -> Result<SubscriptionId, OscError> {
+        let matcher = match Matcher::new(&pattern) {
+            Ok(matcher) => matcher,
+            Err(err) => return Err(OscError::InvalidPattern(pattern, err)),
+        };
+        let mut subscriptions = self
+            .subscriptions
+            .lock()
+            .expect("should be able to lock subscriptions");
+        let id = subscriptions.next_id;
+        subscriptions.next_id = id.wrapping_add(1);
+        subscriptions
+            .patterns
+            .insert(id, Subscription { pattern, matcher });
+        Ok(id)
+    }
+
+    fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.subscriptions
+            .lock()
+            .expect("should be able to lock subscriptions")
+            .patterns
+            .remove(&id)
+            .is_some()
+    }
+}
+
+fn encode_message(path: String, args: Vec<OscValue>) -> Result<OscMessage, OscError> {
+    Ok(OscMessage {
+        addr: path,
+        args: args
+            .into_iter()
+            .map(OscType::try_from)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcOscMessage {
+    path: String,
+    args: Vec<OscValue>,
+    /// names of the targets to send to, empty sends to [`DEFAULT_TARGET`]
+    #[serde(default)]
+    targets: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcOscBundleMessage {
+    path: String,
+    args: Vec<OscValue>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcOscBundle {
+    /// when receivers should apply the bundle, takes precedence over
+    /// `delay_ms`
+    #[serde(default)]
+    timetag: Option<Timetag>,
+    /// apply the bundle this many milliseconds from now
+    #[serde(default)]
+    delay_ms: Option<u64>,
+    messages: Vec<RpcOscBundleMessage>,
+    /// names of the targets to send to, empty sends to [`DEFAULT_TARGET`]
+    #[serde(default)]
+    targets: Vec<String>,
+}
+
+#[command]
+fn send(rpc: RpcOscMessage, state: State<OscPlugin>) -> Result<(), OscError> {
+    state.send(rpc)
+}
+
+/// sends all messages in a single bundle so receivers apply them atomically
+#[command]
+fn send_bundle(rpc: RpcOscBundle, state: State<OscPlugin>) -> Result<(), OscError> {
+    state.send_bundle(rpc)
+}
+
+/// subscribes to inbound messages matching the OSC address `pattern`
+///
+/// matching messages are emitted as `osc_message` events carrying the returned
+/// subscription id
+#[command]
+fn subscribe(pattern: String, state: State<OscPlugin>) -> Result<SubscriptionId, OscError> {
+    state.subscribe(pattern)
+}
+
+#[command]
+fn unsubscribe(id: SubscriptionId, state: State<OscPlugin>) -> bool {
+    state.unsubscribe(id)
+}
+
+/// adds a new target or replaces the existing one called `name`
+#[command]
+async fn set_target(
+    name: String,
+    target: OscTarget,
+    state: State<'_, OscPlugin>,
+) -> Result<(), OscError> {
+    let destination = Destination::open(target, &state.socket).await?;
+    state
+        .targets
+        .write()
+        .expect("should be able to lock targets")
+        .insert(name, destination);
+    Ok(())
+}
+
+#[command]
+fn remove_target(name: String, state: State<OscPlugin>) -> bool {
+    state
+        .targets
+        .write()
+        .expect("should be able to lock targets")
+        .remove(&name)
+        .is_some()
+}
+
+#[command]
+fn get_targets(state: State<OscPlugin>) -> HashMap<String, OscTarget> {
+    state
+        .targets
+        .read()
+        .expect("should be able to lock targets")
+        .iter()
+        .map(|(name, target)| (name.clone(), target.config.clone()))
+        .collect()
+}
+
+/// starts advertising curses' OSC receiver through OSCQuery
+#[command]
+async fn start_oscquery(state: State<'_, OscPlugin>) -> Result<OscQueryInfo, OscError> {
+    let mut oscquery = state
+        .oscquery
+        .lock()
+        .expect("should be able to lock oscquery");
+    if let Some(host) = oscquery.as_ref() {
+        return Ok(host.info().clone());
+    }
+    let osc_addr = state
+        .socket
+        .local_addr()
+        .map_err(|err| OscError::OscQuery(err.to_string()))?;
+    let host = OscQueryHost::start(osc_addr, state.subscriptions.clone())?;
+    let info = host.info().clone();
+    *oscquery = Some(host);
+    Ok(info)
+}
+
+#[command]
+fn stop_oscquery(state: State<OscPlugin>) {
+    state
+        .oscquery
+        .lock()
+        .expect("should be able to lock oscquery")
+        .take();
+}
+
+/// looks for a running VRChat client through OSCQuery and points the
+/// [`DEFAULT_TARGET`] at its OSC input
+#[command]
+async fn discover_vrchat(
+    timeout_ms: Option<u64>,
+    state: State<'_, OscPlugin>,
+) -> Result<OscTarget, OscError> {
+    let timeout = Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_DISCOVERY_TIMEOUT_MS));
+    let target = oscquery::discover_vrchat(timeout).await?;
+    let destination = Destination::open(target.clone(), &state.socket).await?;
+    state
+        .targets
+        .write()
+        .expect("should be able to lock targets")
+        .insert(DEFAULT_TARGET.to_string(), destination);
+    Ok(target)
+}
+
+/// queues `text` for the VRChat chatbox, replacing whatever is still queued
+///
+/// text longer than the chatbox limit is split into pages that are cycled
+/// through, progress is emitted as `osc_chatbox` events
+#[command]
+fn chatbox_submit(text: String, state: State<OscPlugin>) {
+    state.chatbox(ChatboxCommand::Submit(text));
+}
+
+#[command]
+fn chatbox_typing(typing: bool, state: State<OscPlugin>) {
+    state.chatbox(ChatboxCommand::Typing(typing));
+}
+
+#[command]
+fn chatbox_clear(state: State<OscPlugin>) {
+    state.chatbox(ChatboxCommand::Clear);
+}
+
+#[command]
+fn chatbox_configure(options: ChatboxOptions, state: State<OscPlugin>) {
+    state.chatbox(ChatboxCommand::Configure(options));
+}
+
+/// replaces the outputs that follow the speech activity of the transcription
+#[command]
+fn set_speaking_outputs(outputs: Vec<SpeakingOutput>, state: State<OscPlugin>) {
+    *state
+        .speaking_outputs
+        .write()
+        .expect("should be able to lock speaking outputs") = outputs;
+}
+
+#[command]
+fn get_speaking_outputs(state: State<OscPlugin>) -> Vec<SpeakingOutput> {
+    state
+        .speaking_outputs
+        .read()
+        .expect("should be able to lock speaking outputs")
+        .clone()
+}
+
+/// enables TTS lip sync with `options`, or disables it when `None`
+#[command]
+fn set_lip_sync(options: Option<LipSyncOptions>, state: State<OscPlugin>) {
+    *state
+        .lip_sync
+        .write()
+        .expect("should be able to lock lip sync") = options;
+}
+
+#[command]
+fn get_lip_sync(state: State<OscPlugin>) -> Option<LipSyncOptions> {
+    state
+        .lip_sync
+        .read()
+        .expect("should be able to lock lip sync")
+        .clone()
+}
+
+/// sets where VRChat's OSC configs are read from, `None` restores the default
+/// location
+#[command]
+fn set_avatar_directory(directory: Option<PathBuf>, state: State<OscPlugin>) {
+    *state
+        .avatar_directory
+        .write()
+        .expect("should be able to lock avatar directory") = directory;
+}
+
+#[command]
+fn get_avatar_directory<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<OscPlugin>,
+) -> Result<PathBuf, OscError> {
+    state.avatar_directory(&app)
+}
+
+/// parses the OSC configs of every avatar VRChat has written one for
+#[command]
+async fn get_avatar_configs<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, OscPlugin>,
+) -> Result<Vec<AvatarConfig>, OscError> {
+    let directory = state.avatar_directory(&app)?;
+    avatar::read_all(&directory).await
+}
+
+/// the config of the avatar VRChat last reported through `/avatar/change`,
+/// `None` until an avatar change was seen or when its config is missing
+#[command]
+async fn get_active_avatar<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, OscPlugin>,
+) -> Result<Option<AvatarConfig>, OscError> {
+    let active = state
+        .active_avatar
+        .read()
+        .expect("should be able to lock active avatar")
+        .clone();
+    match active {
+        Some(id) => state.avatar_config(&app, &id).await,
+        None => Ok(None),
+    }
+}
+
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new("osc")
+        .invoke_handler(tauri::generate_handler![
+            send,
+            send_bundle,
+            subscribe,
+            unsubscribe,
+            set_target,
+            remove_target,
+            get_targets,
+            start_oscquery,
+            stop_oscquery,
+            discover_vrchat,
+            chatbox_submit,
+            chatbox_typing,
+            chatbox_clear,
+            chatbox_configure,
+            set_speaking_outputs,
+            get_speaking_outputs,
+            set_lip_sync,
+            get_lip_sync,
+            set_avatar_directory,
+            get_avatar_directory,
+            get_avatar_configs,
+            get_active_avatar
+        ])
+        .setup(|app, _api| {
+            let (chatbox, chatbox_rx) = mpsc::unbounded_channel();
+            let plugin = OscPlugin::bind(chatbox)?;
+            let receiver = receive::spawn(
+                app.clone(),
+                plugin.socket.clone(),
+                plugin.subscriptions.clone(),
+                plugin.inbound.clone(),
+            )?;
+            plugin
+                .receiver
+                .set(receiver)
+                .expect("receiver should only be spawned once");
+            let avatar_changes = plugin.listen();
+            app.manage(plugin);
+            tauri::async_runtime::spawn(chatbox::run(app.clone(), chatbox_rx));
+            tauri::async_runtime::spawn(avatar::watch(app.clone(), avatar_changes));
+            Ok(())
+        })
+        .build()
+}
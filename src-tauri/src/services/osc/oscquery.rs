@@ -0,0 +1,479 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+use warp::Filter;
+use warp::http::StatusCode;
+use warp::path::FullPath;
+
+use super::targets::OscTarget;
+use super::{OscError, Subscriptions};
+
+const OSCJSON_SERVICE: &str = "_oscjson._tcp.local.";
+const OSC_SERVICE: &str = "_osc._udp.local.";
+/// instance name prefix VRChat uses for its OSCQuery services
+const VRCHAT_INSTANCE_PREFIX: &str = "VRChat-Client-";
+/// containers that are always part of the tree, VRChat only sends avatar
+/// parameters to services listing `/avatar`
+const ALWAYS_ADVERTISED: &[&str] = &["/avatar"];
+
+/// ACCESS values of the OSCQuery node description
+const ACCESS_NONE: u8 = 0;
+const ACCESS_WRITE: u8 = 2;
+
+/// how long a discovered service may take to answer its `HOST_INFO`
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Clone, Debug)]
+pub struct OscQueryInfo {
+    /// the OSCQuery HTTP port
+    pub http_port: u16,
+    /// the advertised OSC receive port
+    pub osc_port: u16,
+}
+
+/// a running [OSCQuery](https://github.com/Vidvox/OSCQueryProposal) host,
+/// advertises where curses receives OSC and which addresses it listens to
+///
+/// stops advertising when dropped
+pub struct OscQueryHost {
+    info: OscQueryInfo,
+    daemon: ServiceDaemon,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl OscQueryHost {
+    /// serves the OSCQuery HTTP tree for `osc_addr` and advertises it on the
+    /// local network
+    pub fn start(
+        osc_addr: SocketAddr,
+        subscriptions: Arc<Mutex<Subscriptions>>,
+    ) -> Result<Self, OscError> {
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let (http_addr, server) = warp::serve(routes(osc_addr, subscriptions))
+            .try_bind_with_graceful_shutdown(([0, 0, 0, 0], 0), async move {
+                shutdown_rx.await.ok();
+            })
+            .map_err(|err| OscError::OscQuery(err.to_string()))?;
+        tauri::async_runtime::spawn(server);
+
+        let info = OscQueryInfo {
+            http_port: http_addr.port(),
+            osc_port: osc_addr.port(),
+        };
+        let daemon = ServiceDaemon::new().map_err(|err| OscError::OscQuery(err.to_string()))?;
+        let name = format!("curses-{}", info.http_port);
+        let host_name = format!("{name}.local.");
+        for (service, port) in [
+            (OSCJSON_SERVICE, info.http_port),
+            (OSC_SERVICE, info.osc_port),
+        ] {
+            let service = ServiceInfo::new(
+                service,
+                &name,
+                &host_name,
+                "",
+                port,
+                &[("txtvers", "1")][..],
+            )
+            .map_err(|err| OscError::OscQuery(err.to_string()))?
+            .enable_addr_auto();
+            daemon
+                .register(service)
+                .map_err(|err| OscError::OscQuery(err.to_string()))?;
+        }
+        debug!(
+            "advertising oscquery host '{name}' on port {}",
+            info.http_port
+        );
+
+        Ok(OscQueryHost {
+            info,
+            daemon,
+            _shutdown: shutdown,
+        })
+    }
+
+    pub fn info(&self) -> &OscQueryInfo {
+        &self.info
+    }
+}
+
+impl Drop for OscQueryHost {
+    fn drop(&mut self) {
+        if let Err(err) = self.daemon.shutdown() {
+            warn!("could not stop oscquery advertisement: '{err}'");
+        }
+    }
+}
+
+fn routes(
+    osc_addr: SocketAddr,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::get()
+        .and(warp::path::full())
+        .and(warp::query::<HashMap<String, String>>())
+        .map(move |path: FullPath, query: HashMap<String, String>| {
+            if query.contains_key("HOST_INFO") {
+                return warp::reply::with_status(
+                    warp::reply::json(&host_info(osc_addr)),
+                    StatusCode::OK,
+                );
+            }
+            let tree = Node::from_subscriptions(
+                &subscriptions
+                    .lock()
+                    .expect("should be able to lock subscriptions"),
+            );
+            match tree.find(path.as_str()) {
+                Some(node) => warp::reply::with_status(
+                    warp::reply::json(&node.describe(path.as_str())),
+                    StatusCode::OK,
+                ),
+                None => {
+                    warp::reply::with_status(warp::reply::json(&json!({})), StatusCode::NOT_FOUND)
+                }
+            }
+        })
+}
+
+fn host_info(osc_addr: SocketAddr) -> Value {
+    json!({
+        "NAME": "curses",
+        "OSC_IP": osc_addr.ip().to_string(),
+        "OSC_PORT": osc_addr.port(),
+        "OSC_TRANSPORT": "UDP",
+        "EXTENSIONS": {
+            "ACCESS": true,
+            "VALUE": false,
+            "RANGE": false,
+            "DESCRIPTION": false,
+        },
+    })
+}
+
+/// node of the advertised OSC address tree
+#[derive(Default)]
+struct Node {
+    children: BTreeMap<String, Node>,
+    /// whether messages are accepted at exactly this address
+    accepts: bool,
+}
+
+impl Node {
+    /// builds the tree from the literal part of every subscribed pattern
+    fn from_subscriptions(subscriptions: &Subscriptions) -> Self {
+        let mut root = Node::default();
+        for address in ALWAYS_ADVERTISED {
+            root.insert(address, false);
+        }
+        for subscription in subscriptions.patterns.values() {
+            root.insert(&subscription.pattern, true);
+        }
+        root
+    }
+
+    fn insert(&mut self, pattern: &str, accepts: bool) {
+        let mut node = self;
+        let mut literal = true;
+        for part in pattern.split('/').filter(|part| !part.is_empty()) {
+            if part.contains(['*', '?', '[', '{']) {
+                literal = false;
+                break;
+            }
+            node = node.children.entry(part.to_string()).or_default();
+        }
+        node.accepts |= accepts && literal;
+    }
+
+    fn find(&self, path: &str) -> Option<&Node> {
+        path.split('/')
+            .filter(|part| !part.is_empty())
+            .try_fold(self, |node, part| node.children.get(part))
+    }
+
+    fn describe(&self, path: &str) -> Value {
+        let mut description = json!({
+            "FULL_PATH": path,
+            "ACCESS": if self.accepts { ACCESS_WRITE } else { ACCESS_NONE },
+        });
+        if !self.children.is_empty() {
+            let base = path.trim_end_matches('/');
+            description["CONTENTS"] = self
+                .children
+                .iter()
+                .map(|(name, child)| (name.clone(), child.describe(&format!("{base}/{name}"))))
+                .collect::<serde_json::Map<_, _>>()
+                .into();
+        }
+        description
+    }
+}
+
+#[derive(Deserialize)]
+struct HostInfo {
+    /// services listening on every interface leave this out or send
+    /// `0.0.0.0`
+    #[serde(rename = "OSC_IP", default)]
+    osc_ip: Option<String>,
+    #[serde(rename = "OSC_PORT")]
+    osc_port: u16,
+}
+
+/// browses the local network for a VRChat OSCQuery service and asks it where
+/// it receives OSC
+pub async fn discover_vrchat(timeout: Duration) -> Result<OscTarget, OscError> {
+    let daemon = ServiceDaemon::new().map_err(|err| OscError::OscQuery(err.to_string()))?;
+    let result = tokio::time::timeout(timeout, find_vrchat(&daemon)).await;
+    if let Err(err) = daemon.shutdown() {
+        warn!("could not stop oscquery discovery: '{err}'");
+    }
+    result.map_err(|_| OscError::OscQuery("no VRChat client found".to_string()))?
+}
+
+async fn find_vrchat(daemon: &ServiceDaemon) -> Result<OscTarget, OscError> {
+    let events = daemon
+        .browse(OSCJSON_SERVICE)
+        .map_err(|err| OscError::OscQuery(err.to_string()))?;
+    let client = query_client(QUERY_TIMEOUT)?;
+    while let Ok(event) = events.recv_async().await {
+        let ServiceEvent::ServiceResolved(info) = event else {
+            continue;
+        };
+        if !info.get_fullname().starts_with(VRCHAT_INSTANCE_PREFIX) {
+            continue;
+        }
+        // prefer IPv4, that is what VRChat binds its OSC input to
+        let Some(ip) = info
+            .get_addresses()
+            .iter()
+            .min_by_key(|ip| !ip.is_ipv4())
+            .copied()
+        else {
+            continue;
+        };
+        match query_target(&client, ip, info.get_port()).await {
+            Ok(target) => {
+                debug!("found VRChat oscquery service '{}'", info.get_fullname());
+                return Ok(target);
+            }
+            Err(err) => warn!(
+                "could not query oscquery service '{}': '{err}'",
+                info.get_fullname()
+            ),
+        }
+    }
+    Err(OscError::OscQuery("mdns browsing stopped".to_string()))
+}
+
+fn query_client(timeout: Duration) -> Result<reqwest::Client, OscError> {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|err| OscError::OscQuery(err.to_string()))
+}
+
+/// where the OSCQuery service at `ip` and `port` receives OSC, on `ip` unless
+/// it names a specific address
+async fn query_target(
+    client: &reqwest::Client,
+    ip: IpAddr,
+    port: u16,
+) -> Result<OscTarget, OscError> {
+    let host = query_host_info(client, ip, port)
+        .await
+        .map_err(|err| OscError::OscQuery(err.to_string()))?;
+    let osc_ip = host.osc_ip.filter(|osc_ip| {
+        osc_ip
+            .parse::<IpAddr>()
+            .is_ok_and(|osc_ip| !osc_ip.is_unspecified())
+    });
+    Ok(OscTarget {
+        host: osc_ip.unwrap_or_else(|| ip.to_string()),
+        port: host.osc_port,
+        bind: None,
+    })
+}
+
+async fn query_host_info(
+    client: &reqwest::Client,
+    ip: IpAddr,
+    port: u16,
+) -> reqwest::Result<HostInfo> {
+    client
+        .get(format!("http://{}/?HOST_INFO", SocketAddr::new(ip, port)))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<HostInfo>()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use rosc::address::Matcher;
+    use serde_json::{Value, json};
+    use tokio::time::Instant;
+    use warp::filters::BoxedFilter;
+    use warp::reply::Response;
+    use warp::{Filter, Reply};
+
+    use super::super::{Subscription, Subscriptions};
+    use super::{query_client, query_target, routes};
+
+    const LOCALHOST: Ipv4Addr = Ipv4Addr::LOCALHOST;
+    const OSC_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(LOCALHOST), 9001);
+
+    /// serves `filter` on a free localhost port
+    fn serve(filter: BoxedFilter<(Response,)>) -> u16 {
+        let (addr, server) = warp::serve(filter).bind_ephemeral((LOCALHOST, 0));
+        tokio::spawn(server);
+        addr.port()
+    }
+
+    /// an OSCQuery service answering every request with `host_info`
+    fn mock(host_info: Value) -> u16 {
+        serve(
+            warp::any()
+                .map(move || warp::reply::json(&host_info).into_response())
+                .boxed(),
+        )
+    }
+
+    /// the tree curses itself serves for `patterns`
+    fn host(patterns: &[&str]) -> u16 {
+        let mut subscriptions = Subscriptions::default();
+        for (id, pattern) in patterns.iter().enumerate() {
+            subscriptions.patterns.insert(
+                id as u32,
+                Subscription {
+                    pattern: pattern.to_string(),
+                    matcher: Matcher::new(pattern).expect("pattern should be valid"),
+                },
+            );
+        }
+        serve(
+            routes(OSC_ADDR, Arc::new(Mutex::new(subscriptions)))
+                .map(Reply::into_response)
+                .boxed(),
+        )
+    }
+
+    fn client() -> reqwest::Client {
+        query_client(Duration::from_secs(2)).expect("client should build")
+    }
+
+    async fn get(port: u16, path: &str) -> (u16, Value) {
+        let response = client()
+            .get(format!("http://{LOCALHOST}:{port}{path}"))
+            .send()
+            .await
+            .expect("request should succeed");
+        let status = response.status().as_u16();
+        (status, response.json().await.expect("should be json"))
+    }
+
+    #[tokio::test]
+    async fn reads_own_host_info() {
+        let port = host(&[]);
+        let target = query_target(&client(), LOCALHOST.into(), port)
+            .await
+            .expect("host info should parse");
+        assert_eq!(target.host, "127.0.0.1");
+        assert_eq!(target.port, 9001);
+    }
+
+    #[tokio::test]
+    async fn describes_subscribed_addresses() {
+        let port = host(&["/avatar/parameters/Mute", "/chatbox/*"]);
+
+        let (status, root) = get(port, "/").await;
+        assert_eq!(status, 200);
+        assert_eq!(root["CONTENTS"]["avatar"]["ACCESS"], json!(0));
+        // wildcards are not advertised as addresses of their own
+        assert_eq!(
+            root["CONTENTS"]["chatbox"],
+            json!({ "FULL_PATH": "/chatbox", "ACCESS": 0 })
+        );
+
+        let (status, avatar) = get(port, "/avatar").await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            avatar["CONTENTS"]["parameters"]["CONTENTS"]["Mute"],
+            json!({ "FULL_PATH": "/avatar/parameters/Mute", "ACCESS": 2 })
+        );
+
+        let (status, _) = get(port, "/unknown").await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn reads_vrchat_host_info() {
+        let port = mock(json!({
+            "NAME": "VRChat-Client-ABC123",
+            "OSC_IP": "127.0.0.2",
+            "OSC_PORT": 9000,
+            "OSC_TRANSPORT": "UDP",
+        }));
+        let target = query_target(&client(), LOCALHOST.into(), port)
+            .await
+            .expect("host info should parse");
+        assert_eq!(target.host, "127.0.0.2");
+        assert_eq!(target.port, 9000);
+        assert!(target.bind.is_none());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_service_address() {
+        for host_info in [
+            json!({ "OSC_PORT": 9000 }),
+            json!({ "OSC_IP": "0.0.0.0", "OSC_PORT": 9000 }),
+        ] {
+            let port = mock(host_info);
+            let target = query_target(&client(), LOCALHOST.into(), port)
+                .await
+                .expect("host info should parse");
+            assert_eq!(target.host, "127.0.0.1");
+            assert_eq!(target.port, 9000);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_host_info_without_port() {
+        let port = mock(json!({ "OSC_IP": "127.0.0.1" }));
+        let target = query_target(&client(), LOCALHOST.into(), port).await;
+        assert!(target.is_err());
+    }
+
+    #[tokio::test]
+    async fn times_out_on_silent_service() {
+        let listener = tokio::net::TcpListener::bind((LOCALHOST, 0))
+            .await
+            .expect("should bind");
+        let port = listener.local_addr().expect("should be bound").port();
+        // accepts connections but never answers
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.push(connection);
+            }
+        });
+
+        let client = query_client(Duration::from_millis(200)).expect("client should build");
+        let started = Instant::now();
+        let target = query_target(&client, LOCALHOST.into(), port).await;
+        assert!(target.is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
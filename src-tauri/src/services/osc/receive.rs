@@ -0,0 +1,129 @@
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rosc::address::OscAddress;
+use rosc::{OscMessage, OscPacket, OscTime, decoder};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Runtime};
+use tokio::sync::broadcast;
+use tracing::{debug, error, trace, warn};
+
+use super::value::{OscValue, Timetag};
+use super::{SubscriptionId, Subscriptions};
+
+/// biggest payload a single UDP datagram can carry
+const MAX_DATAGRAM: usize = 65_536;
+
+/// payload of the `osc_message` event
+#[derive(Serialize, Clone, Debug)]
+struct InboundOscMessage {
+    subscription: SubscriptionId,
+    path: String,
+    args: Vec<OscValue>,
+    /// timetag of the enclosing bundle, `None` when the message was not part
+    /// of a bundle
+    timetag: Option<Timetag>,
+}
+
+/// Spawns the thread that decodes everything arriving on `socket`, emits the
+/// messages matching any of the `subscriptions` to the frontend and passes all
+/// of them on to `inbound`
+pub fn spawn<R: Runtime>(
+    app: AppHandle<R>,
+    socket: Arc<UdpSocket>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    inbound: broadcast::Sender<OscMessage>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name("osc-receive".to_string())
+        .spawn(move || {
+            let mut buf = vec![0u8; MAX_DATAGRAM];
+            loop {
+                let size = match socket.recv_from(&mut buf) {
+                    Ok((size, _)) => size,
+                    // windows reports unreachable destinations of earlier sends on the socket
+                    Err(err) if err.kind() == ErrorKind::ConnectionReset => continue,
+                    Err(err) => {
+                        error!("osc receiver stopped: '{err}'");
+                        return;
+                    }
+                };
+                let packet = match decoder::decode_udp(&buf[..size]) {
+                    Ok((_, packet)) => packet,
+                    Err(err) => {
+                        debug!("dropping undecodable osc packet: '{err}'");
+                        continue;
+                    }
+                };
+                let mut messages = Vec::new();
+                flatten(packet, None, &mut messages);
+                for (message, timetag) in messages {
+                    if inbound.receiver_count() > 0 {
+                        // only fails when the last listener went away in the meantime
+                        let _ = inbound.send(message.clone());
+                    }
+                    dispatch(&app, &subscriptions, message, timetag);
+                }
+            }
+        })
+}
+
+/// unpacks (nested) bundles into their messages, each message keeps the
+/// timetag of its innermost bundle
+fn flatten(
+    packet: OscPacket,
+    timetag: Option<OscTime>,
+    out: &mut Vec<(OscMessage, Option<OscTime>)>,
+) {
+    match packet {
+        OscPacket::Message(message) => out.push((message, timetag)),
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                flatten(packet, Some(bundle.timetag), out);
+            }
+        }
+    }
+}
+
+fn dispatch<R: Runtime>(
+    app: &AppHandle<R>,
+    subscriptions: &Mutex<Subscriptions>,
+    message: OscMessage,
+    timetag: Option<OscTime>,
+) {
+    let Ok(address) = OscAddress::new(message.addr.clone()) else {
+        debug!(
+            "dropping osc message with invalid address '{}'",
+            message.addr
+        );
+        return;
+    };
+    let matched: Vec<SubscriptionId> = subscriptions
+        .lock()
+        .expect("should be able to lock subscriptions")
+        .patterns
+        .iter()
+        .filter(|(_, subscription)| subscription.matcher.match_address(&address))
+        .map(|(id, _)| *id)
+        .collect();
+    if matched.is_empty() {
+        return;
+    }
+
+    let args: Vec<OscValue> = message.args.into_iter().map(OscValue::from).collect();
+    trace!("received osc message to '{}'", message.addr);
+
+    for subscription in matched {
+        let payload = InboundOscMessage {
+            subscription,
+            path: message.addr.clone(),
+            args: args.clone(),
+            timetag: timetag.map(Timetag::from),
+        };
+        if app.emit("osc_message", payload).is_err() {
+            warn!("wasn't able to emit osc message to frontend");
+        }
+    }
+}
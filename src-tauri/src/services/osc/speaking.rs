@@ -0,0 +1,38 @@
+use rosc::{OscMessage, OscType};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::OscPlugin;
+use super::chatbox::ChatboxCommand;
+
+/// where speech activity detected by the VAD gets reported to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SpeakingOutput {
+    /// bool avatar parameter (or any other address) that is true while speaking
+    Parameter {
+        path: String,
+        /// names of the targets to send to, empty sends to the default target
+        #[serde(default)]
+        targets: Vec<String>,
+    },
+    /// the chatbox typing indicator
+    ChatboxTyping,
+}
+
+impl SpeakingOutput {
+    pub fn apply(&self, plugin: &OscPlugin, speaking: bool) {
+        match self {
+            SpeakingOutput::Parameter { path, targets } => {
+                let message = OscMessage {
+                    addr: path.clone(),
+                    args: vec![OscType::Bool(speaking)],
+                };
+                if let Err(err) = plugin.send_message(message, targets) {
+                    warn!("could not report speech activity to '{path}': '{err}'");
+                }
+            }
+            SpeakingOutput::ChatboxTyping => plugin.chatbox(ChatboxCommand::Typing(speaking)),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::OscError;
+
+/// name of the target messages go to when they don't select any
+pub const DEFAULT_TARGET: &str = "default";
+/// VRChat listens for OSC input on this port by default
+pub const DEFAULT_TARGET_PORT: u16 = 9000;
+
+/// user supplied description of an OSC destination
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OscTarget {
+    pub host: String,
+    pub port: u16,
+    /// local address to send from, picked automatically when not set
+    #[serde(default)]
+    pub bind: Option<String>,
+}
+
+/// a resolved [`OscTarget`] together with the socket used to reach it
+pub struct Destination {
+    pub config: OscTarget,
+    pub addr: SocketAddr,
+    pub socket: Arc<UdpSocket>,
+}
+
+impl Destination {
+    /// resolves `config` and opens a socket for it
+    ///
+    /// IPv4 loopback destinations without an explicit bind address share the
+    /// plugin socket, so that replies to it reach the OSC receiver
+    pub async fn open(config: OscTarget, shared: &Arc<UdpSocket>) -> Result<Self, OscError> {
+        let addr = tokio::net::lookup_host((config.host.as_str(), config.port))
+            .await
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| OscError::Resolve(format!("{}:{}", config.host, config.port)))?;
+
+        let socket = match &config.bind {
+            Some(bind) => Arc::new(bind_socket(bind)?),
+            None if addr.is_ipv4() && addr.ip().is_loopback() => shared.clone(),
+            None if addr.is_ipv4() => Arc::new(bind_socket("0.0.0.0:0")?),
+            None => Arc::new(bind_socket("[::]:0")?),
+        };
+        Ok(Destination {
+            config,
+            addr,
+            socket,
+        })
+    }
+
+    /// the target that mirrors the historic hardcoded destination
+    pub fn default_target(shared: &Arc<UdpSocket>) -> Self {
+        let addr = SocketAddr::from(([127, 0, 0, 1], DEFAULT_TARGET_PORT));
+        Destination {
+            config: OscTarget {
+                host: addr.ip().to_string(),
+                port: addr.port(),
+                bind: None,
+            },
+            addr,
+            socket: shared.clone(),
+        }
+    }
+}
+
+fn bind_socket(bind: &str) -> Result<UdpSocket, OscError> {
+    UdpSocket::bind(bind).map_err(|err| OscError::Bind(bind.to_string(), err))
+}
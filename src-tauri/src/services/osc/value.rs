@@ -0,0 +1,176 @@
+use std::time::{Duration, SystemTime};
+
+use rosc::{OscArray, OscColor, OscMidiMessage, OscTime, OscType};
+use serde::{Deserialize, Serialize};
+
+use super::OscError;
+
+/// NTP timestamp as used by OSC bundles and the `t` argument type
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timetag {
+    pub seconds: u32,
+    pub fractional: u32,
+}
+
+impl Timetag {
+    /// the special timetag telling the receiver to act upon arrival
+    pub const IMMEDIATELY: Timetag = Timetag {
+        seconds: 0,
+        fractional: 1,
+    };
+
+    /// the timetag `delay` from now
+    pub fn after(delay: Duration) -> Result<Self, OscError> {
+        let time = OscTime::try_from(SystemTime::now() + delay)
+            .map_err(|_| OscError::OutOfRange("timetag outside of the NTP era".to_string()))?;
+        Ok(time.into())
+    }
+}
+
+impl From<OscTime> for Timetag {
+    fn from(time: OscTime) -> Self {
+        Timetag {
+            seconds: time.seconds,
+            fractional: time.fractional,
+        }
+    }
+}
+
+impl From<Timetag> for OscTime {
+    fn from(time: Timetag) -> Self {
+        OscTime {
+            seconds: time.seconds,
+            fractional: time.fractional,
+        }
+    }
+}
+
+/// OSC argument as exchanged with the frontend
+///
+/// `Float` and `Int` are the 32bit OSC types, the frontend passes them as
+/// 64bit values which are checked for being representable before sending
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum OscValue {
+    Bool(bool),
+    Float(f64),
+    Int(i64),
+    String(String),
+    Double(f64),
+    Long(i64),
+    Blob(Vec<u8>),
+    Char(char),
+    Color {
+        red: u8,
+        green: u8,
+        blue: u8,
+        alpha: u8,
+    },
+    Midi {
+        port: u8,
+        status: u8,
+        data1: u8,
+        data2: u8,
+    },
+    Time(Timetag),
+    Nil,
+    Inf,
+    Array(Vec<OscValue>),
+}
+
+impl From<OscType> for OscValue {
+    fn from(value: OscType) -> Self {
+        match value {
+            OscType::Bool(v) => OscValue::Bool(v),
+            OscType::Float(v) => OscValue::Float(v.into()),
+            OscType::Int(v) => OscValue::Int(v.into()),
+            OscType::String(v) => OscValue::String(v),
+            OscType::Double(v) => OscValue::Double(v),
+            OscType::Long(v) => OscValue::Long(v),
+            OscType::Blob(v) => OscValue::Blob(v),
+            OscType::Char(v) => OscValue::Char(v),
+            OscType::Color(OscColor {
+                red,
+                green,
+                blue,
+                alpha,
+            }) => OscValue::Color {
+                red,
+                green,
+                blue,
+                alpha,
+            },
+            OscType::Midi(OscMidiMessage {
+                port,
+                status,
+                data1,
+                data2,
+            }) => OscValue::Midi {
+                port,
+                status,
+                data1,
+                data2,
+            },
+            OscType::Time(v) => OscValue::Time(v.into()),
+            OscType::Nil => OscValue::Nil,
+            OscType::Inf => OscValue::Inf,
+            OscType::Array(v) => OscValue::Array(v.content.into_iter().map(Into::into).collect()),
+        }
+    }
+}
+
+impl TryFrom<OscValue> for OscType {
+    type Error = OscError;
+
+    fn try_from(value: OscValue) -> Result<Self, Self::Error> {
+        Ok(match value {
+            OscValue::Bool(v) => OscType::Bool(v),
+            OscValue::Float(v) => {
+                // precision loss is expected, overflowing into infinity is not
+                if v.is_finite() && v.abs() > f64::from(f32::MAX) {
+                    return Err(OscError::OutOfRange(format!("{v} does not fit a float")));
+                }
+                OscType::Float(v as f32)
+            }
+            OscValue::Int(v) => OscType::Int(
+                i32::try_from(v)
+                    .map_err(|_| OscError::OutOfRange(format!("{v} does not fit an int")))?,
+            ),
+            OscValue::String(v) => OscType::String(v),
+            OscValue::Double(v) => OscType::Double(v),
+            OscValue::Long(v) => OscType::Long(v),
+            OscValue::Blob(v) => OscType::Blob(v),
+            OscValue::Char(v) => OscType::Char(v),
+            OscValue::Color {
+                red,
+                green,
+                blue,
+                alpha,
+            } => OscType::Color(OscColor {
+                red,
+                green,
+                blue,
+                alpha,
+            }),
+            OscValue::Midi {
+                port,
+                status,
+                data1,
+                data2,
+            } => OscType::Midi(OscMidiMessage {
+                port,
+                status,
+                data1,
+                data2,
+            }),
+            OscValue::Time(v) => OscType::Time(v.into()),
+            OscValue::Nil => OscType::Nil,
+            OscValue::Inf => OscType::Inf,
+            OscValue::Array(v) => OscType::Array(OscArray {
+                content: v
+                    .into_iter()
+                    .map(OscType::try_from)
+                    .collect::<Result<_, _>>()?,
+            }),
+        })
+    }
+}
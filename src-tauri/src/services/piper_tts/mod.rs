@@ -2,19 +2,22 @@ use core::str;
 use std::fs;
 use std::io::{self, Cursor};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::{Context, bail};
 use futures::TryFutureExt;
 use rodio::Decoder;
 use serde::{Deserialize, Serialize};
-use tauri::{Manager, Runtime, State, plugin};
+use tauri::{AppHandle, Manager, Runtime, State, plugin};
 use tempfile::TempDir;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::ChildStdout;
 use tokio::sync::Mutex;
 use tracing::{debug, trace};
 
-use super::audio::{IndependentSink, get_independent_sink};
+use super::audio::{IndependentSink, get_independent_sink, lip_synced};
+use super::metrics::Metrics;
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Voice {
@@ -130,11 +133,47 @@ async fn get_wav_bytes(text: &str, state: &State<'_, PiperInstance>) -> anyhow::
 }
 
 #[derive(Default)]
-struct PiperInstance {
+pub struct PiperInstance {
     process: Mutex<Option<(tokio::process::Child, BufReader<ChildStdout>, TempDir)>>,
     sink: Mutex<Option<IndependentSink>>,
 }
 
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PiperStatus {
+    pub running: bool,
+    pub process_alive: bool,
+    pub sink_alive: bool,
+}
+
+impl PiperStatus {
+    pub fn healthy(&self) -> bool {
+        !self.running || (self.process_alive && self.sink_alive)
+    }
+}
+
+impl PiperInstance {
+    /// a held lock means speech is being generated or played, which counts as
+    /// alive
+    pub fn status(&self) -> PiperStatus {
+        let (running, process_alive) = match self.process.try_lock() {
+            Ok(mut process) => match process.as_mut() {
+                Some(child) => (true, matches!(child.0.try_wait(), Ok(None))),
+                None => (false, false),
+            },
+            Err(_) => (true, true),
+        };
+        let sink_alive = match self.sink.try_lock() {
+            Ok(sink) => sink.as_ref().is_some_and(IndependentSink::is_alive),
+            Err(_) => true,
+        };
+        PiperStatus {
+            running,
+            process_alive,
+            sink_alive,
+        }
+    }
+}
+
 #[tauri::command]
 fn get_voices(path: PathBuf) -> Result<Vec<Voice>, String> {
     if path.to_string_lossy().is_empty() {
@@ -148,22 +187,29 @@ fn get_voices(path: PathBuf) -> Result<Vec<Voice>, String> {
 }
 
 #[tauri::command]
-async fn speak(
+async fn speak<R: Runtime>(
+    app: AppHandle<R>,
     args: PiperArgs,
     text: String,
     state: State<'_, PiperInstance>,
 ) -> Result<(), String> {
     use crate::services::audio::RpcAudioPlayAsync;
 
+    let metrics = app.state::<Arc<Metrics>>();
+
     // current piper impl breaks if input contains newlines
     for line in text.lines() {
         // fast path for empty string
         if line.is_empty() {
             continue;
         }
+        let started = Instant::now();
         let bytes = get_wav_bytes(line, &state)
             .await
             .map_err(|e| e.to_string())?;
+        metrics
+            .piper_synthesis_seconds
+            .observe_duration(started.elapsed());
 
         let sink_lock = state.sink.lock().await;
         let Some(sink) = sink_lock.as_ref() else {
@@ -179,7 +225,7 @@ async fn speak(
 
         match Decoder::new(Cursor::new(play_async_args.data)) {
             Ok(source) => {
-                sink.inner.append(source);
+                sink.inner.append(lip_synced(&app, source));
                 sink.inner.sleep_until_end();
                 continue;
             }
@@ -0,0 +1,113 @@
+use std::fmt::Write;
+
+use serde::Deserialize;
+
+use super::{Entry, Session, TranscriptError};
+
+/// captions without interim results would flash by, they stay at least this
+/// long unless the next one starts
+const MIN_CUE_MS: u64 = 1500;
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Srt,
+    Vtt,
+    Txt,
+    Json,
+}
+
+pub fn render(session: &Session, format: ExportFormat) -> Result<String, TranscriptError> {
+    let mut out = String::new();
+    let written = match format {
+        ExportFormat::Srt => srt(&mut out, &session.entries),
+        ExportFormat::Vtt => vtt(&mut out, &session.entries),
+        ExportFormat::Txt => txt(&mut out, &session.entries),
+        ExportFormat::Json => return Ok(serde_json::to_string_pretty(session)?),
+    };
+    written.expect("writing to a string should not fail");
+    Ok(out)
+}
+
+/// `hh:mm:ss` followed by `separator` and milliseconds, if any
+fn timestamp(ms: u64, separator: Option<char>) -> String {
+    let (hours, minutes, seconds) = (ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60);
+    match separator {
+        Some(separator) => format!(
+            "{hours:02}:{minutes:02}:{seconds:02}{separator}{:03}",
+            ms % 1000
+        ),
+        None => format!("{hours:02}:{minutes:02}:{seconds:02}"),
+    }
+}
+
+/// start and end of the cue of `entries[index]`
+fn cue(entries: &[Entry], index: usize) -> (u64, u64) {
+    let entry = &entries[index];
+    let mut end = entry.end_ms.max(entry.start_ms + MIN_CUE_MS);
+    if let Some(next) = entries
+        .get(index + 1)
+        .filter(|next| next.start_ms > entry.start_ms)
+    {
+        end = end.min(next.start_ms);
+    }
+    (entry.start_ms, end)
+}
+
+/// the text and translation of `entry` as cue lines, blank lines would end
+/// the cue early
+fn cue_text(entry: &Entry) -> String {
+    std::iter::once(&entry.text)
+        .chain(&entry.translation)
+        .flat_map(|text| text.lines())
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn srt(out: &mut String, entries: &[Entry]) -> std::fmt::Result {
+    for (index, entry) in entries.iter().enumerate() {
+        let (start, end) = cue(entries, index);
+        writeln!(out, "{}", index + 1)?;
+        writeln!(
+            out,
+            "{} --> {}",
+            timestamp(start, Some(',')),
+            timestamp(end, Some(','))
+        )?;
+        writeln!(out, "{}\n", cue_text(entry))?;
+    }
+    Ok(())
+}
+
+fn vtt(out: &mut String, entries: &[Entry]) -> std::fmt::Result {
+    writeln!(out, "WEBVTT\n")?;
+    for (index, entry) in entries.iter().enumerate() {
+        let (start, end) = cue(entries, index);
+        // cue text is markup, `-->` would be taken for timings
+        let text = cue_text(entry)
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        writeln!(
+            out,
+            "{} --> {}",
+            timestamp(start, Some('.')),
+            timestamp(end, Some('.'))
+        )?;
+        writeln!(out, "{text}\n")?;
+    }
+    Ok(())
+}
+
+fn txt(out: &mut String, entries: &[Entry]) -> std::fmt::Result {
+    for entry in entries {
+        let time = timestamp(entry.start_ms, None);
+        writeln!(out, "[{time}] {}", entry.text)?;
+        if let Some(translation) = &entry.translation {
+            writeln!(out, "[{time}] {translation}")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use export::ExportFormat;
+use serde::{Deserialize, Serialize};
+use tauri::async_runtime::Mutex;
+use tauri::plugin::{Builder, TauriPlugin};
+use tauri::{Manager, Runtime, State, command};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use super::web::{TextEvent, TextEvents, TextSource};
+
+mod export;
+
+/// sessions are stored as JSON lines, one [`Record`] per line
+const EXTENSION: &str = "jsonl";
+
+#[derive(Error, Debug)]
+pub enum TranscriptError {
+    #[error("unknown transcript session '{0}'")]
+    UnknownSession(String),
+    #[error("could not access transcripts: '{0}'")]
+    Io(#[from] std::io::Error),
+    #[error("could not encode transcript: '{0}'")]
+    Encode(#[from] serde_json::Error),
+    #[error("transcript task failed: '{0}'")]
+    Task(#[from] tauri::Error),
+}
+
+impl Serialize for TranscriptError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// a final caption, times are relative to the start of its session
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Entry {
+    pub id: usize,
+    /// when the first interim result of the caption arrived, or the final one
+    /// for sources without interim results
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub source: TextSource,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translation: Option<String>,
+}
+
+impl Entry {
+    /// whether the text or translation contains the lowercase `query`
+    fn matches(&self, query: &str) -> bool {
+        std::iter::once(&self.text)
+            .chain(&self.translation)
+            .any(|text| text.to_lowercase().contains(query))
+    }
+}
+
+/// a line of a session file, translations arrive after the caption they
+/// belong to
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Record {
+    Entry(Entry),
+    Translation { entry: usize, text: String },
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Session {
+    /// unix time in milliseconds the session started at, also its file name
+    pub id: String,
+    pub entries: Vec<Entry>,
+}
+
+impl Session {
+    fn read(path: &Path, id: String) -> std::io::Result<Self> {
+        let mut entries: Vec<Entry> = Vec::new();
+        for line in std::fs::read_to_string(path)?.lines() {
+            // the last line may be cut off when the app did not exit cleanly
+            match serde_json::from_str(line) {
+                Ok(Record::Entry(entry)) => entries.push(entry),
+                Ok(Record::Translation { entry, text }) => {
+                    if let Some(entry) = entries.iter_mut().rev().find(|other| other.id == entry) {
+                        entry.translation = Some(text);
+                    }
+                }
+                Err(err) => debug!("skipping transcript line in '{}': '{err}'", path.display()),
+            }
+        }
+        Ok(Session { id, entries })
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SessionInfo {
+    pub id: String,
+    pub entries: usize,
+    /// end of the last entry
+    pub duration_ms: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchHit {
+    pub session: String,
+    pub entry: Entry,
+}
+
+/// the session currently being recorded
+struct Recording {
+    id: String,
+    started: Instant,
+    /// opened with the first entry, so runs without captions leave no file
+    file: Option<tokio::fs::File>,
+    next_entry: usize,
+    /// latest caption a translation would belong to
+    untranslated: Option<usize>,
+    /// when each source's current caption got its first interim result
+    speaking_since: HashMap<TextSource, u64>,
+}
+
+impl Recording {
+    fn new() -> Self {
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+            .to_string();
+        Recording {
+            id,
+            started: Instant::now(),
+            file: None,
+            next_entry: 0,
+            untranslated: None,
+            speaking_since: HashMap::new(),
+        }
+    }
+
+    async fn append(&mut self, directory: &Path, record: &Record) -> Result<(), TranscriptError> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        if self.file.is_none() {
+            tokio::fs::create_dir_all(directory).await?;
+            let file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(session_path(directory, &self.id))
+                .await?;
+            self.file = Some(file);
+        }
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(line.as_bytes()).await?;
+            // tokio only hands the bytes to a blocking write, flushing waits
+            // for it so exports see the line and errors are not lost
+            file.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// records final captions, interim ones only mark when a caption started
+    async fn apply(&mut self, directory: &Path, event: TextEvent) -> Result<(), TranscriptError> {
+        let TextEvent::Text {
+            source,
+            interim,
+            value,
+        } = event
+        else {
+            return Ok(());
+        };
+        let now = self.started.elapsed().as_millis() as u64;
+        if interim {
+            self.speaking_since.entry(source).or_insert(now);
+            return Ok(());
+        }
+        let start_ms = self.speaking_since.remove(&source).unwrap_or(now);
+        let text = value.trim().to_string();
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(entry) = self
+            .untranslated
+            .take_if(|_| source == TextSource::Translation)
+        {
+            return self
+                .append(directory, &Record::Translation { entry, text })
+                .await;
+        }
+        let id = self.next_entry;
+        self.next_entry += 1;
+        if source != TextSource::Translation {
+            self.untranslated = Some(id);
+        }
+        let entry = Entry {
+            id,
+            start_ms,
+            end_ms: now,
+            source,
+            text,
+            translation: None,
+        };
+        self.append(directory, &Record::Entry(entry)).await
+    }
+}
+
+struct Transcripts {
+    directory: PathBuf,
+    recording: Mutex<Recording>,
+}
+
+impl Transcripts {
+    fn session_ids(&self) -> std::io::Result<Vec<String>> {
+        let entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut ids = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let id = path.file_stem().and_then(|stem| stem.to_str()).filter(|_| {
+                path.extension()
+                    .is_some_and(|extension| extension == EXTENSION)
+            });
+            if let Some(id) = id {
+                ids.push(id.to_string());
+            }
+        }
+        // ids are timestamps, newest first
+        ids.sort_by_key(|id| std::cmp::Reverse(id.parse::<u64>().unwrap_or_default()));
+        Ok(ids)
+    }
+
+    fn session(&self, id: &str) -> Result<Session, TranscriptError> {
+        // ids come from the frontend, keep them from naming other files
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+            return Err(TranscriptError::UnknownSession(id.to_string()));
+        }
+        let path = session_path(&self.directory, id);
+        if !path.is_file() {
+            return Err(TranscriptError::UnknownSession(id.to_string()));
+        }
+        Ok(Session::read(&path, id.to_string())?)
+    }
+}
+
+fn session_path(directory: &Path, id: &str) -> PathBuf {
+    directory.join(id).with_extension(EXTENSION)
+}
+
+async fn record(transcripts: Arc<Transcripts>, mut events: broadcast::Receiver<TextEvent>) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("transcript skipped {skipped} text events");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let mut recording = transcripts.recording.lock().await;
+        if let Err(err) = recording.apply(&transcripts.directory, event).await {
+            warn!("could not record transcript: '{err}'");
+        }
+    }
+}
+
+/// runs `read` on the blocking pool, reading long histories would stall the
+/// thread it runs on
+async fn read_blocking<T: Send + 'static>(
+    transcripts: &Arc<Transcripts>,
+    read: impl FnOnce(&Transcripts) -> Result<T, TranscriptError> + Send + 'static,
+) -> Result<T, TranscriptError> {
+    let transcripts = transcripts.clone();
+    tauri::async_runtime::spawn_blocking(move || read(&transcripts)).await?
+}
+
+/// recorded sessions, newest first, sessions that cannot be read are left out
+#[command]
+async fn list_sessions(
+    state: State<'_, Arc<Transcripts>>,
+) -> Result<Vec<SessionInfo>, TranscriptError> {
+    read_blocking(&state, |transcripts| {
+        let sessions = transcripts
+            .session_ids()?
+            .into_iter()
+            .filter_map(|id| match transcripts.session(&id) {
+                Ok(session) => Some(SessionInfo {
+                    entries: session.entries.len(),
+                    duration_ms: session
+                        .entries
+                        .last()
+                        .map(|entry| entry.end_ms)
+                        .unwrap_or_default(),
+                    id,
+                }),
+                Err(err) => {
+                    warn!("skipping transcript session '{id}': '{err}'");
+                    None
+                }
+            })
+            .collect();
+        Ok(sessions)
+    })
+    .await
+}
+
+/// entries whose text or translation contains `query`, ignoring case, in
+/// `session` or every readable session when `None`
+#[command]
+async fn search(
+    query: String,
+    session: Option<String>,
+    state: State<'_, Arc<Transcripts>>,
+) -> Result<Vec<SearchHit>, TranscriptError> {
+    let query = query.to_lowercase();
+    read_blocking(&state, move |transcripts| {
+        let all = session.is_none();
+        let ids = match session {
+            Some(id) => vec![id],
+            None => transcripts.session_ids()?,
+        };
+        let mut hits = Vec::new();
+        for id in ids {
+            let session = match transcripts.session(&id) {
+                Ok(session) => session,
+                Err(err) if all => {
+                    warn!("skipping transcript session '{id}': '{err}'");
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            hits.extend(
+                session
+                    .entries
+                    .into_iter()
+                    .filter(|entry| entry.matches(&query))
+                    .map(|entry| SearchHit {
+                        session: id.clone(),
+                        entry,
+                    }),
+            );
+        }
+        Ok(hits)
+    })
+    .await
+}
+
+/// the session as subtitles or a document, for saving by the frontend
+#[command]
+async fn export(
+    session: String,
+    format: ExportFormat,
+    state: State<'_, Arc<Transcripts>>,
+) -> Result<String, TranscriptError> {
+    read_blocking(&state, move |transcripts| {
+        export::render(&transcripts.session(&session)?, format)
+    })
+    .await
+}
+
+/// ends the current session, the next caption starts a new one
+///
+/// the new session is started even when the old one could not be flushed
+#[command]
+async fn new_session(state: State<'_, Arc<Transcripts>>) -> Result<String, TranscriptError> {
+    let mut recording = state.recording.lock().await;
+    let flushed = match recording.file.as_mut() {
+        Some(file) => file.flush().await,
+        None => Ok(()),
+    };
+    *recording = Recording::new();
+    flushed?;
+    Ok(recording.id.clone())
+}
+
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new("transcript")
+        .invoke_handler(tauri::generate_handler![
+            list_sessions,
+            search,
+            export,
+            new_session
+        ])
+        .setup(|app, _api| {
+            let transcripts = Arc::new(Transcripts {
+                directory: app.path().app_data_dir()?.join("transcripts"),
+                recording: Mutex::new(Recording::new()),
+            });
+            let events = app.state::<TextEvents>().subscribe();
+            tauri::async_runtime::spawn(record(transcripts.clone(), events));
+            app.manage(transcripts);
+            Ok(())
+        })
+        .build()
+}
@@ -1,9 +1,9 @@
 use futures::TryFutureExt;
 use serde::{Deserialize, Serialize};
 use tauri::plugin::{Builder, TauriPlugin};
-use tauri::{Runtime, command};
+use tauri::{AppHandle, Runtime, command};
 
-use crate::services::audio::{RpcAudioPlayAsync, play_async};
+use crate::services::audio::{RpcAudioPlayAsync, play_async_lip_synced};
 
 #[derive(Serialize, Deserialize, Debug)]
 struct UberDuckAuth {
@@ -50,7 +50,7 @@ async fn get_voices(auth: UberDuckAuth) -> Result<Vec<Voice>, String> {
 }
 
 #[command]
-async fn speak(data: UberduckRequest) -> Result<(), String> {
+async fn speak<R: Runtime>(app: AppHandle<R>, data: UberduckRequest) -> Result<(), String> {
     let client = reqwest::Client::new();
     if let Ok(resp) = client
         .post("https://api.uberduck.ai/speak-synchronous")
@@ -63,12 +63,15 @@ async fn speak(data: UberduckRequest) -> Result<(), String> {
         .and_then(|f| f.bytes())
         .await
     {
-        play_async(RpcAudioPlayAsync {
-            device_name: data.device_name,
-            data: resp.to_vec(),
-            volume: data.volume,
-            rate: 1.0,
-        })
+        play_async_lip_synced(
+            &app,
+            RpcAudioPlayAsync {
+                device_name: data.device_name,
+                data: resp.to_vec(),
+                volume: data.volume,
+                rate: 1.0,
+            },
+        )
         .await
     } else {
         Err("Request failed".to_string())
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use anyhow::Context;
+use rand::Rng;
+use rand::distr::Alphanumeric;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::{debug, warn};
+use warp::http::header::{
+    ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_MAX_AGE, SET_COOKIE, VARY,
+};
+use warp::http::uri::Authority;
+use warp::http::{HeaderValue, StatusCode};
+use warp::reject::Reject;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+use super::network;
+use super::tls::Certificate;
+
+/// query parameter carrying the access token, PeerJS sends its `key` option
+/// under this name
+pub const TOKEN_PARAMETER: &str = "key";
+/// cookie remembering the token, so pages opened with it in their url can load
+/// their assets
+const TOKEN_COOKIE: &str = "curses_key";
+const TOKEN_LENGTH: usize = 32;
+/// where the token is persisted, in the app data directory
+const TOKEN_FILE: &str = "access_token.txt";
+/// where the HTTPS certificate is persisted, in the app data directory
+const TLS_DIRECTORY: &str = "tls";
+
+/// origins of the app's own webview
+const APP_ORIGINS: &[&str] = &[
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+];
+/// origin of the webview in development, served by vite
+const DEV_ORIGIN: &str = "http://localhost:1420";
+/// headers allowed in cross origin requests when the preflight asks for none
+const CORS_HEADERS: &str = "authorization, content-type";
+/// how long browsers may cache a preflight response, in seconds
+const CORS_MAX_AGE: &str = "600";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AccessOptions {
+    /// only serve on the loopback interface
+    pub localhost_only: bool,
+    /// origins besides the server itself and the app that may use it, `*`
+    /// allows any origin
+    pub allowed_origins: Vec<String>,
+    /// additionally serve HTTPS on this port, with a self signed certificate
+    pub https_port: Option<u16>,
+}
+
+/// where and how the server listens
+#[derive(Clone)]
+pub struct Listen {
+    pub ip: IpAddr,
+    pub https: Option<(u16, Arc<Certificate>)>,
+}
+
+impl Listen {
+    fn https_port(&self) -> Option<u16> {
+        self.https.as_ref().map(|(port, _)| *port)
+    }
+}
+
+/// who may use the web server
+///
+/// requests need to present the access token unless they come from this
+/// machine through a local address, requests made by browsers need to come
+/// from an allowed origin
+pub struct Access {
+    token: RwLock<String>,
+    /// where the token is persisted
+    token_path: PathBuf,
+    /// port HTTP is served on
+    port: u16,
+    options: RwLock<AccessOptions>,
+    listen: watch::Sender<Listen>,
+    /// where the HTTPS certificate is persisted
+    certificate_directory: PathBuf,
+    certificate: Mutex<Option<Arc<Certificate>>>,
+}
+
+impl Access {
+    /// reads the access token persisted in `data_directory`, generating and
+    /// persisting a new one the first time
+    ///
+    /// the token is kept across restarts so links other devices use keep
+    /// working
+    pub fn load_or_generate(port: u16, data_directory: &Path) -> Self {
+        let token_path = data_directory.join(TOKEN_FILE);
+        let token = match std::fs::read_to_string(&token_path) {
+            Ok(token) if valid_token(token.trim()) => token.trim().to_string(),
+            _ => {
+                let token = generate_token();
+                if let Err(err) = persist_token(&token_path, &token) {
+                    warn!("could not persist access token: '{err:#}'");
+                }
+                token
+            }
+        };
+        let options = AccessOptions::default();
+        Access {
+            token: RwLock::new(token),
+            token_path,
+            port,
+            listen: watch::Sender::new(Listen {
+                ip: bind_ip(&options),
+                https: None,
+            }),
+            options: RwLock::new(options),
+            certificate_directory: data_directory.join(TLS_DIRECTORY),
+            certificate: Mutex::new(None),
+        }
+    }
+
+    pub fn token(&self) -> String {
+        self.token
+            .read()
+            .expect("should be able to lock access token")
+            .clone()
+    }
+
+    /// replaces the access token, links and cookies with the old one stop
+    /// working
+    pub fn regenerate_token(&self) -> anyhow::Result<String> {
+        let token = generate_token();
+        persist_token(&self.token_path, &token)?;
+        *self
+            .token
+            .write()
+            .expect("should be able to lock access token") = token.clone();
+        debug!("regenerated access token");
+        Ok(token)
+    }
+
+    pub fn options(&self) -> AccessOptions {
+        self.options
+            .read()
+            .expect("should be able to lock access options")
+            .clone()
+    }
+
+    /// replaces the options, the server rebinds when that changes the
+    /// interface or ports it has to listen on
+    ///
+    /// fails when HTTPS gets enabled but no certificate could be loaded
+    pub fn set_options(&self, options: AccessOptions) -> anyhow::Result<()> {
+        let listen = Listen {
+            ip: bind_ip(&options),
+            https: match options.https_port {
+                Some(port) => Some((port, self.certificate()?)),
+                None => None,
+            },
+        };
+        *self
+            .options
+            .write()
+            .expect("should be able to lock access options") = options;
+        self.listen.send_if_modified(|current| {
+            let changed = current.ip != listen.ip || current.https_port() != listen.https_port();
+            *current = listen;
+            changed
+        });
+        Ok(())
+    }
+
+    /// follows where the server has to listen
+    pub fn listen(&self) -> watch::Receiver<Listen> {
+        self.listen.subscribe()
+    }
+
+    /// fingerprint of the HTTPS certificate, `None` while HTTPS is disabled
+    pub fn fingerprint(&self) -> Option<String> {
+        self.listen
+            .borrow()
+            .https
+            .as_ref()
+            .map(|(_, certificate)| certificate.fingerprint.clone())
+    }
+
+    fn certificate(&self) -> anyhow::Result<Arc<Certificate>> {
+        let mut certificate = self
+            .certificate
+            .lock()
+            .expect("should be able to lock certificate");
+        if let Some(certificate) = certificate.as_ref() {
+            return Ok(certificate.clone());
+        }
+        let loaded = Arc::new(Certificate::load_or_create(&self.certificate_directory)?);
+        *certificate = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    /// whether `host`, the `Host` header, names this server by a local address
+    ///
+    /// the header is up to the client, a page whose domain got rebound to this
+    /// machine sends its own domain
+    fn local_host(&self, host: &str) -> bool {
+        let Ok(authority) = host.parse::<Authority>() else {
+            return false;
+        };
+        let https_port = self.listen.borrow().https_port();
+        let port_matches = match authority.port_u16() {
+            Some(port) => port == self.port || Some(port) == https_port,
+            None => self.port == 80 || https_port == Some(443),
+        };
+        let name = authority
+            .host()
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        port_matches
+            && (name.eq_ignore_ascii_case("localhost") || name.parse().is_ok_and(network::is_local))
+    }
+
+    /// the app itself or a page served by this server, `host` is only set
+    /// when it is local
+    fn own_origin(&self, origin: &str, host: Option<&str>) -> bool {
+        APP_ORIGINS.contains(&origin)
+            || (cfg!(debug_assertions) && origin == DEV_ORIGIN)
+            || host.is_some_and(|host| {
+                origin
+                    .split_once("://")
+                    .is_some_and(|(_, authority)| authority == host)
+            })
+    }
+
+    fn allowlisted(&self, origin: &str) -> bool {
+        self.options
+            .read()
+            .expect("should be able to lock access options")
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == origin)
+    }
+
+    fn check(
+        &self,
+        remote: Option<SocketAddr>,
+        origin: Option<String>,
+        host: Option<String>,
+        authorization: Option<String>,
+        cookie: Option<String>,
+        query: HashMap<String, String>,
+    ) -> Result<Grant, Rejection> {
+        let host = host.as_deref().filter(|host| self.local_host(host));
+        // only the app and its own pages get by without the token on loopback,
+        // allowed origins are pages of other sites
+        let trusted = match origin.as_deref() {
+            Some(origin) => self.own_origin(origin, host),
+            None => host.is_some(),
+        };
+        if origin
+            .as_deref()
+            .is_some_and(|origin| !trusted && !self.allowlisted(origin))
+        {
+            return Err(warp::reject::custom(Denied::Origin));
+        }
+
+        let token = self.token();
+        let token = token.as_str();
+        let from_query = query
+            .get(TOKEN_PARAMETER)
+            .is_some_and(|key| token_matches(key, token));
+        let presented = from_query
+            || cookie.is_some_and(|cookie| token_matches(&cookie, token))
+            || authorization.is_some_and(|value| {
+                value
+                    .strip_prefix("Bearer ")
+                    .is_some_and(|bearer| token_matches(bearer, token))
+            });
+        let loopback = remote.is_some_and(|remote| remote.ip().is_loopback());
+        if !presented && !(loopback && trusted) {
+            return Err(warp::reject::custom(Denied::Token));
+        }
+
+        Ok(Grant {
+            set_cookie: from_query
+                .then(|| format!("{TOKEN_COOKIE}={token}; Path=/; HttpOnly; SameSite=Strict")),
+            allow_origin: origin,
+        })
+    }
+}
+
+/// compares every byte instead of stopping at the first difference, so
+/// response times do not tell how much of a guessed token was right
+fn token_matches(presented: &str, token: &str) -> bool {
+    presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn generate_token() -> String {
+    rand::rng()
+        .sample_iter(Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// whether a persisted token is one [`generate_token`] could have made
+fn valid_token(token: &str) -> bool {
+    token.len() == TOKEN_LENGTH && token.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn persist_token(path: &Path, token: &str) -> anyhow::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("could not create '{}'", directory.display()))?;
+    }
+    std::fs::write(path, token).with_context(|| format!("could not write '{}'", path.display()))
+}
+
+fn bind_ip(options: &AccessOptions) -> IpAddr {
+    if options.localhost_only {
+        Ipv4Addr::LOCALHOST.into()
+    } else {
+        Ipv4Addr::UNSPECIFIED.into()
+    }
+}
+
+#[derive(Debug)]
+enum Denied {
+    Origin,
+    Token,
+}
+
+impl Reject for Denied {}
+
+/// response adjustments for a request that passed the [`guard`]
+pub struct Grant {
+    set_cookie: Option<String>,
+    allow_origin: Option<String>,
+}
+
+/// rejects requests that are not allowed to use the server
+pub fn guard(access: Arc<Access>) -> impl Filter<Extract = (Grant,), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and(warp::header::optional::<String>("origin"))
+        .and(warp::header::optional::<String>("host"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::cookie::optional::<String>(TOKEN_COOKIE))
+        .and(
+            warp::query::<HashMap<String, String>>()
+                .or(warp::any().map(HashMap::new))
+                .unify(),
+        )
+        .and_then(move |remote, origin, host, authorization, cookie, query| {
+            futures::future::ready(access.check(remote, origin, host, authorization, cookie, query))
+        })
+}
+
+/// answers CORS preflight requests from origins that may use the server
+///
+/// browsers send these without cookies or an `Authorization` header, so they
+/// have to be answered before the [`guard`] asks for the token
+pub fn preflight(
+    access: Arc<Access>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    warp::options()
+        .and(warp::header::<String>("origin"))
+        .and(warp::header::<String>("access-control-request-method"))
+        .and(warp::header::optional::<String>(
+            "access-control-request-headers",
+        ))
+        .and(warp::header::optional::<String>("host"))
+        .and_then(
+            move |origin: String,
+                  _method: String,
+                  headers: Option<String>,
+                  host: Option<String>| {
+                let host = host.as_deref().filter(|host| access.local_host(host));
+                let allowed = access.own_origin(&origin, host) || access.allowlisted(&origin);
+                futures::future::ready(if allowed {
+                    Ok(preflight_response(origin, headers))
+                } else {
+                    Err(warp::reject::custom(Denied::Origin))
+                })
+            },
+        )
+}
+
+fn preflight_response(origin: String, headers: Option<String>) -> Response {
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    let response_headers = response.headers_mut();
+    if let Ok(origin) = HeaderValue::try_from(origin) {
+        response_headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    }
+    let headers = headers
+        .and_then(|headers| HeaderValue::try_from(headers).ok())
+        .unwrap_or(HeaderValue::from_static(CORS_HEADERS));
+    response_headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, headers);
+    response_headers.insert(
+        ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, POST"),
+    );
+    response_headers.insert(
+        ACCESS_CONTROL_MAX_AGE,
+        HeaderValue::from_static(CORS_MAX_AGE),
+    );
+    response_headers.append(VARY, HeaderValue::from_static("Origin"));
+    response
+}
+
+/// applies the [`Grant`] to the response of a guarded route
+pub fn finish(grant: Grant, reply: impl Reply) -> Response {
+    let mut response = reply.into_response();
+    let headers = response.headers_mut();
+    if let Some(cookie) = grant
+        .set_cookie
+        .and_then(|cookie| HeaderValue::try_from(cookie).ok())
+    {
+        headers.insert(SET_COOKIE, cookie);
+    }
+    if let Some(origin) = grant
+        .allow_origin
+        .and_then(|origin| HeaderValue::try_from(origin).ok())
+    {
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.append(VARY, HeaderValue::from_static("Origin"));
+    }
+    response
+}
+
+pub async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    match rejection.find::<Denied>() {
+        Some(Denied::Origin) => Ok(warp::reply::with_status(
+            "origin not allowed",
+            StatusCode::FORBIDDEN,
+        )),
+        Some(Denied::Token) => Ok(warp::reply::with_status(
+            "missing or invalid access token",
+            StatusCode::UNAUTHORIZED,
+        )),
+        None => Err(rejection),
+    }
+}
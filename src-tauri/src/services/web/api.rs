@@ -0,0 +1,266 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::{AppHandle, Emitter, Runtime};
+use tokio::sync::mpsc;
+use tracing::warn;
+use warp::body::BodyDeserializeError;
+use warp::filters::BoxedFilter;
+use warp::http::header::ALLOW;
+use warp::http::{HeaderValue, StatusCode};
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply};
+
+use super::text::TextSource;
+
+/// largest request body the api accepts
+const BODY_LIMIT: u64 = 16 * 1024;
+
+/// a route of the web server, for the listing at `GET /api`
+#[derive(Serialize)]
+pub struct RouteInfo {
+    pub method: &'static str,
+    pub path: &'static str,
+    pub description: &'static str,
+    /// example request body
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<&'static str>,
+}
+
+pub const ROUTES: &[RouteInfo] = &[
+    RouteInfo {
+        method: "GET",
+        path: "/ping",
+        description: "answers with `pong`",
+        body: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/status",
+        description: "state of whisper, piper, the OSC socket and connected clients, `503` while a service died",
+        body: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/metrics",
+        description: "speech pipeline and pubsub metrics in the Prometheus text format",
+        body: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/peer",
+        description: "PeerJS signaling websocket",
+        body: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/pubsub",
+        description: "pubsub websocket, `?id=<peer id>&topics=<comma separated topics>&since=<seq>&latest=true`",
+        body: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/files/<path>",
+        description: "files of the media directory set with `set_media_directory`",
+        body: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/api",
+        description: "lists the routes of the server",
+        body: None,
+    },
+    RouteInfo {
+        method: "POST",
+        path: "/api/text",
+        description: "publishes a text event, `source` is one of `stt`, `textfield` or `translation`",
+        body: Some(r#"{"value": "hello", "interim": false, "source": "textfield"}"#),
+    },
+    RouteInfo {
+        method: "POST",
+        path: "/api/tts",
+        description: "speaks the text with the active text to speech service",
+        body: Some(r#"{"value": "hello"}"#),
+    },
+    RouteInfo {
+        method: "POST",
+        path: "/api/captions/clear",
+        description: "clears the text shown by all text elements",
+        body: None,
+    },
+    RouteInfo {
+        method: "POST",
+        path: "/api/stt/start",
+        description: "starts speech to text with the configured backend, like whisper",
+        body: None,
+    },
+    RouteInfo {
+        method: "POST",
+        path: "/api/stt/stop",
+        description: "stops speech to text",
+        body: None,
+    },
+];
+
+#[derive(Deserialize)]
+struct TextBody {
+    value: String,
+    /// interim results get replaced by the next text event
+    #[serde(default)]
+    interim: bool,
+    #[serde(default)]
+    source: TextSource,
+}
+
+#[derive(Deserialize)]
+struct TtsBody {
+    value: String,
+}
+
+/// payload of the `web_api` event, carried out by the frontend
+#[derive(Serialize, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Action {
+    Tts { value: String },
+    ClearCaptions,
+    SttStart,
+    SttStop,
+}
+
+/// the REST api, `output` is where the pubsub websocket delivers messages to
+/// the frontend
+pub fn path<R: Runtime>(
+    app: AppHandle<R>,
+    output: mpsc::Sender<String>,
+) -> BoxedFilter<(impl Reply,)> {
+    let listing = warp::path!("api")
+        .and(warp::get())
+        .map(|| warp::reply::json(&json!({ "routes": ROUTES })).into_response());
+
+    let text = warp::path!("api" / "text")
+        .and(warp::post())
+        .and(json_body::<TextBody>())
+        .then(move |body: TextBody| {
+            let output = output.clone();
+            async move {
+                // same shape as the text events of the pubsub websocket, the
+                // type is `TextEventType` on the frontend
+                let message = json!({
+                    "topic": body.source.topic(),
+                    "data": { "type": u8::from(body.interim), "value": body.value },
+                });
+                match output.send(message.to_string()).await {
+                    Ok(()) => ok(),
+                    Err(_) => failure(StatusCode::SERVICE_UNAVAILABLE, "frontend is not running"),
+                }
+            }
+        });
+
+    let tts_app = app.clone();
+    let tts = warp::path!("api" / "tts")
+        .and(warp::post())
+        .and(json_body::<TtsBody>())
+        .map(move |body: TtsBody| emit(&tts_app, Action::Tts { value: body.value }));
+
+    let clear_app = app.clone();
+    let clear = warp::path!("api" / "captions" / "clear")
+        .and(warp::post())
+        .map(move || emit(&clear_app, Action::ClearCaptions));
+
+    let start_app = app.clone();
+    let stt_start = warp::path!("api" / "stt" / "start")
+        .and(warp::post())
+        .map(move || emit(&start_app, Action::SttStart));
+
+    let stt_stop = warp::path!("api" / "stt" / "stop")
+        .and(warp::post())
+        .map(move || emit(&app, Action::SttStop));
+
+    listing
+        .or(text)
+        .unify()
+        .or(tts)
+        .unify()
+        .or(clear)
+        .unify()
+        .or(stt_start)
+        .unify()
+        .or(stt_stop)
+        .unify()
+        .recover(handle_rejection)
+        .or(unmatched())
+        .boxed()
+}
+
+/// answers api requests no route took with json, rather than letting them
+/// fall through to the app's `index.html`
+fn unmatched() -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone {
+    warp::path("api")
+        .and(warp::path::full())
+        .map(|path: FullPath| {
+            let methods = ROUTES
+                .iter()
+                .filter(|route| route.path == path.as_str())
+                .map(|route| route.method)
+                .collect::<Vec<_>>()
+                .join(", ");
+            if methods.is_empty() {
+                return failure(StatusCode::NOT_FOUND, "unknown api route");
+            }
+            let mut response = failure(
+                StatusCode::METHOD_NOT_ALLOWED,
+                &format!("method not allowed, use {methods}"),
+            );
+            if let Ok(methods) = HeaderValue::try_from(methods) {
+                response.headers_mut().insert(ALLOW, methods);
+            }
+            response
+        })
+}
+
+fn json_body<T: DeserializeOwned + Send>() -> impl Filter<Extract = (T,), Error = Rejection> + Clone
+{
+    warp::body::content_length_limit(BODY_LIMIT).and(warp::body::json())
+}
+
+fn emit<R: Runtime>(app: &AppHandle<R>, action: Action) -> warp::reply::Response {
+    match app.emit("web_api", action) {
+        Ok(()) => ok(),
+        Err(err) => {
+            warn!("wasn't able to emit api request to frontend: '{err}'");
+            failure(StatusCode::SERVICE_UNAVAILABLE, "frontend is not running")
+        }
+    }
+}
+
+fn ok() -> warp::reply::Response {
+    warp::reply::json(&json!({ "ok": true })).into_response()
+}
+
+fn failure(status: StatusCode, message: &str) -> warp::reply::Response {
+    warp::reply::with_status(
+        warp::reply::json(&json!({ "ok": false, "error": message })),
+        status,
+    )
+    .into_response()
+}
+
+/// answers malformed api requests with json, other rejections fall through to
+/// [`unmatched`]
+async fn handle_rejection(rejection: Rejection) -> Result<warp::reply::Response, Rejection> {
+    if let Some(err) = rejection.find::<BodyDeserializeError>() {
+        Ok(failure(StatusCode::BAD_REQUEST, &err.to_string()))
+    } else if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+        Ok(failure(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "request body too large",
+        ))
+    } else if rejection.find::<warp::reject::LengthRequired>().is_some() {
+        Ok(failure(
+            StatusCode::LENGTH_REQUIRED,
+            "request body needs a content length",
+        ))
+    } else {
+        Err(rejection)
+    }
+}
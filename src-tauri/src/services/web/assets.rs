@@ -1,46 +1,324 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
 use tauri::{AssetResolver, Runtime};
+use tokio::sync::OnceCell;
+use tracing::warn;
 use warp::filters::BoxedFilter;
 use warp::http::header::*;
-use warp::http::{HeaderValue, Response, StatusCode};
+use warp::http::{HeaderMap, HeaderValue, Response, StatusCode};
+use warp::hyper::Body;
+use warp::hyper::body::Bytes;
 use warp::path::FullPath;
 use warp::{Filter, Rejection, Reply};
 
+/// smaller assets are not worth compressing
+const MIN_COMPRESS_SIZE: usize = 1024;
+/// vite puts a content hash into the name of everything it emits here
+const HASHED_ASSETS: &str = "/assets/";
+
 pub fn path<R: Runtime>(resolver: Arc<AssetResolver<R>>) -> BoxedFilter<(impl Reply,)> {
+    let cache = Arc::new(AssetCache::default());
     warp::path::full()
-        .and_then(move |path: FullPath| file_response(path, resolver.clone()))
+        .and(warp::header::headers_cloned())
+        .and_then(move |path: FullPath, headers: HeaderMap| {
+            file_response(path, headers, resolver.clone(), cache.clone())
+        })
         .boxed()
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    fn token(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+}
+
+/// a resolved asset with its validator and compressed variants
+struct Cached {
+    bytes: Bytes,
+    mime_type: String,
+    /// hash of the uncompressed bytes, without quotes
+    hash: String,
+    /// `None` once compressing turned out not to save anything
+    gzip: OnceCell<Option<Bytes>>,
+    brotli: OnceCell<Option<Bytes>>,
+}
+
+impl Cached {
+    fn new(bytes: Vec<u8>, mime_type: String) -> Self {
+        let hash = Sha256::digest(&bytes)[..16]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Cached {
+            bytes: bytes.into(),
+            mime_type,
+            hash,
+            gzip: OnceCell::new(),
+            brotli: OnceCell::new(),
+        }
+    }
+
+    fn etag(&self, encoding: Encoding) -> String {
+        match encoding {
+            Encoding::Identity => format!("\"{}\"", self.hash),
+            _ => format!("\"{}-{}\"", self.hash, encoding.token()),
+        }
+    }
+
+    fn compressible(&self) -> bool {
+        self.bytes.len() >= MIN_COMPRESS_SIZE && compressible(&self.mime_type)
+    }
+
+    /// the bytes in `encoding`, compressed on first use
+    async fn encoded(&self, encoding: Encoding) -> Option<Bytes> {
+        let cell = match encoding {
+            Encoding::Identity => return Some(self.bytes.clone()),
+            Encoding::Gzip => &self.gzip,
+            Encoding::Brotli => &self.brotli,
+        };
+        cell.get_or_init(|| {
+            let bytes = self.bytes.clone();
+            async move {
+                let original = bytes.len();
+                let compressed =
+                    tauri::async_runtime::spawn_blocking(move || compress(&bytes, encoding))
+                        .await
+                        .map_err(|err| err.to_string())
+                        .and_then(|result| result.map_err(|err| err.to_string()));
+                match compressed {
+                    Ok(compressed) => (compressed.len() < original).then(|| compressed.into()),
+                    Err(err) => {
+                        warn!("could not compress asset: '{err}'");
+                        None
+                    }
+                }
+            }
+        })
+        .await
+        .clone()
+    }
+}
+
+/// assets are embedded into the binary, so what got resolved once stays valid
+#[derive(Default)]
+struct AssetCache {
+    entries: Mutex<HashMap<String, Arc<Cached>>>,
+}
+
+impl AssetCache {
+    fn get<R: Runtime>(&self, resolver: &AssetResolver<R>, path: &str) -> Option<Arc<Cached>> {
+        if let Some(cached) = self
+            .entries
+            .lock()
+            .expect("should be able to lock asset cache")
+            .get(path)
+        {
+            return Some(cached.clone());
+        }
+        let asset = resolver.get(path.to_string())?;
+        let cached = Arc::new(Cached::new(asset.bytes, asset.mime_type));
+        self.entries
+            .lock()
+            .expect("should be able to lock asset cache")
+            .insert(path.to_string(), cached.clone());
+        Some(cached)
+    }
+}
+
+fn compress(bytes: &[u8], encoding: Encoding) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Identity => Ok(bytes.to_vec()),
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(bytes)?;
+            encoder.finish()
+        }
+        Encoding::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 9, 22);
+            encoder.write_all(bytes)?;
+            Ok(encoder.into_inner())
+        }
+    }
+}
+
+fn compressible(mime_type: &str) -> bool {
+    const TYPES: &[&str] = &[
+        "application/javascript",
+        "application/json",
+        "application/wasm",
+        "application/xml",
+        "image/svg+xml",
+        "font/otf",
+        "font/ttf",
+    ];
+    mime_type.starts_with("text/") || TYPES.iter().any(|kind| mime_type.starts_with(kind))
+}
+
+/// whether the `Accept-Encoding` header allows `coding`
+fn accepts(accept_encoding: &str, coding: &str) -> bool {
+    accept_encoding.split(',').any(|part| {
+        let mut params = part.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let quality = params
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|quality| quality.trim().parse::<f32>().ok())
+            .unwrap_or(1.);
+        name.eq_ignore_ascii_case(coding) && quality > 0.
+    })
+}
+
+/// whether an `If-None-Match` header names `etag`, weakly
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// whether an `If-Range` header names `etag`, which takes a strong comparison
+/// and no wildcard, dates never match
+pub fn if_range_matches(header: &str, etag: &str) -> bool {
+    let tag = header.trim();
+    !tag.starts_with("W/") && !etag.starts_with("W/") && tag == etag
+}
+
+pub enum ByteRange {
+    Satisfiable(Range<usize>),
+    Unsatisfiable,
+}
+
+/// the range of a `Range` header, `None` for headers that are invalid or ask
+/// for several ranges, these get the whole file
+pub fn byte_range(header: &str, len: usize) -> Option<ByteRange> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: usize = suffix.parse().ok()?;
+            if suffix == 0 {
+                return Some(ByteRange::Unsatisfiable);
+            }
+            len.saturating_sub(suffix)..len
+        }
+        (start, "") => start.parse().ok()?..len,
+        (start, end) => {
+            let start: usize = start.parse().ok()?;
+            let end: usize = end.parse().ok()?;
+            if end < start {
+                return None;
+            }
+            start..end.saturating_add(1).min(len)
+        }
+    };
+    if range.start >= len || range.is_empty() {
+        return Some(ByteRange::Unsatisfiable);
+    }
+    Some(ByteRange::Satisfiable(range))
+}
+
+pub fn header<'a>(headers: &'a HeaderMap, name: HeaderName) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
 async fn file_response<R: Runtime>(
     path: FullPath,
+    headers: HeaderMap,
     resolver: Arc<AssetResolver<R>>,
-) -> Result<impl Reply, Rejection> {
-    if let Some(asset) = resolver.get(path.as_str().into()) {
-        Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header(ACCEPT_RANGES, HeaderValue::from_static("bytes"))
-            .header(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"))
-            .header(CONTENT_TYPE, asset.mime_type)
-            .header(
-                CONTENT_SECURITY_POLICY,
-                HeaderValue::from_static("frame-ancestors *"),
-            )
-            .header(X_FRAME_OPTIONS, HeaderValue::from_static("ALLOW-FROM *"))
-            .body(asset.bytes))
-    } else if let Some(index) = resolver.get("/index.html".into()) {
-        Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header(ACCEPT_RANGES, HeaderValue::from_static("bytes"))
-            .header(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"))
-            .header(
-                CONTENT_SECURITY_POLICY,
-                HeaderValue::from_static("frame-ancestors *"),
-            )
-            .header(X_FRAME_OPTIONS, HeaderValue::from_static("ALLOW-FROM *"))
-            .body(index.bytes))
+    cache: Arc<AssetCache>,
+) -> Result<Response<Body>, Rejection> {
+    let (cached, cache_control) = if let Some(asset) = cache.get(&resolver, path.as_str()) {
+        let cache_control = if path.as_str().starts_with(HASHED_ASSETS) {
+            "public, max-age=31536000, immutable"
+        } else {
+            "no-cache"
+        };
+        (asset, cache_control)
+    } else if let Some(index) = cache.get(&resolver, "/index.html") {
+        (index, "no-cache")
     } else {
-        Err(warp::reject::not_found())
+        return Err(warp::reject::not_found());
+    };
+
+    let range = header(&headers, RANGE).and_then(|range| byte_range(range, cached.bytes.len()));
+    // ranges refer to the uncompressed bytes
+    let mut encoding = Encoding::Identity;
+    let mut body = cached.bytes.clone();
+    if range.is_none() && cached.compressible() {
+        let accept_encoding = header(&headers, ACCEPT_ENCODING).unwrap_or_default();
+        for candidate in [Encoding::Brotli, Encoding::Gzip] {
+            if !accepts(accept_encoding, candidate.token()) {
+                continue;
+            }
+            if let Some(encoded) = cached.encoded(candidate).await {
+                encoding = candidate;
+                body = encoded;
+                break;
+            }
+        }
     }
+    let etag = cached.etag(encoding);
+
+    let mut response = Response::builder()
+        .header(ACCEPT_RANGES, HeaderValue::from_static("bytes"))
+        .header(CONTENT_TYPE, cached.mime_type.as_str())
+        .header(
+            CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static("frame-ancestors *"),
+        )
+        .header(X_FRAME_OPTIONS, HeaderValue::from_static("ALLOW-FROM *"))
+        .header(CACHE_CONTROL, cache_control)
+        .header(ETAG, etag.as_str())
+        .header(VARY, HeaderValue::from_static("Accept-Encoding"));
+
+    if header(&headers, IF_NONE_MATCH).is_some_and(|tags| etag_matches(tags, &etag)) {
+        return Ok(response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .expect("response should be valid"));
+    }
+
+    let range = range.filter(|_| {
+        header(&headers, IF_RANGE).is_none_or(|if_range| if_range_matches(if_range, &etag))
+    });
+    let len = cached.bytes.len();
+    let response = match range {
+        Some(ByteRange::Satisfiable(range)) => response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                CONTENT_RANGE,
+                format!("bytes {}-{}/{len}", range.start, range.end - 1),
+            )
+            .body(Body::from(body.slice(range))),
+        Some(ByteRange::Unsatisfiable) => response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(CONTENT_RANGE, format!("bytes */{len}"))
+            .body(Body::empty()),
+        None => {
+            if encoding != Encoding::Identity {
+                response = response.header(CONTENT_ENCODING, encoding.token());
+            }
+            response.status(StatusCode::OK).body(Body::from(body))
+        }
+    };
+    Ok(response.expect("response should be valid"))
 }
@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::SinkExt;
+use futures::stream::SplitSink;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use warp::ws::{Message, WebSocket};
+
+/// what happens when a client does not keep up with its messages
+#[derive(Clone, Copy, Debug)]
+pub enum Overflow {
+    /// drop the oldest queued message to make room
+    DropOldest,
+    /// close the connection
+    Disconnect,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// messages queued per client before [`Overflow`] applies
+    pub queue: usize,
+    pub overflow: Overflow,
+    /// larger messages from clients get dropped, in bytes
+    pub max_message_size: usize,
+    /// messages a client may send per second on average
+    pub rate: f64,
+    /// messages a client may send in a row before the rate applies
+    pub burst: f64,
+}
+
+/// counters of one websocket endpoint
+#[derive(Default)]
+pub struct Stats {
+    clients: AtomicU64,
+    dropped: AtomicU64,
+    disconnected: AtomicU64,
+    oversized: AtomicU64,
+    rate_limited: AtomicU64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StatsSnapshot {
+    /// clients connected right now
+    pub clients: u64,
+    /// messages that never reached a client or the app because a queue was
+    /// full
+    pub dropped: u64,
+    /// clients disconnected for not keeping up
+    pub disconnected: u64,
+    /// messages from clients dropped for exceeding the size limit
+    pub oversized: u64,
+    /// messages from clients dropped for exceeding the rate limit
+    pub rate_limited: u64,
+}
+
+impl Stats {
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            clients: self.clients.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            disconnected: self.disconnected.load(Ordering::Relaxed),
+            oversized: self.oversized.load(Ordering::Relaxed),
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn count_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct Shared {
+    queue: Mutex<VecDeque<Message>>,
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+/// bounded queue of the messages for one client, a separate task writes them
+/// to its socket
+///
+/// dropping the outbox closes the socket once the queue is written
+pub struct Outbox {
+    shared: Arc<Shared>,
+    limits: Limits,
+    stats: Arc<Stats>,
+}
+
+impl Outbox {
+    pub fn spawn(sink: SplitSink<WebSocket, Message>, limits: Limits, stats: Arc<Stats>) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+            closed: AtomicBool::new(false),
+        });
+        tauri::async_runtime::spawn(write(sink, shared.clone()));
+        stats.clients.fetch_add(1, Ordering::Relaxed);
+        Outbox {
+            shared,
+            limits,
+            stats,
+        }
+    }
+
+    /// queues `message`, `false` once the client is disconnected
+    pub fn push(&self, message: Message) -> bool {
+        if self.shared.closed.load(Ordering::Acquire) {
+            return false;
+        }
+        {
+            let mut queue = self
+                .shared
+                .queue
+                .lock()
+                .expect("should be able to lock outbox");
+            if queue.len() >= self.limits.queue {
+                match self.limits.overflow {
+                    Overflow::DropOldest => {
+                        queue.pop_front();
+                        self.stats.count_dropped();
+                    }
+                    Overflow::Disconnect => {
+                        queue.clear();
+                        drop(queue);
+                        self.stats.disconnected.fetch_add(1, Ordering::Relaxed);
+                        self.close();
+                        return false;
+                    }
+                }
+            }
+            queue.push_back(message);
+        }
+        self.shared.notify.notify_one();
+        true
+    }
+
+    fn close(&self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.notify.notify_one();
+    }
+}
+
+impl Drop for Outbox {
+    fn drop(&mut self) {
+        self.stats.clients.fetch_sub(1, Ordering::Relaxed);
+        self.close();
+    }
+}
+
+async fn write(mut sink: SplitSink<WebSocket, Message>, shared: Arc<Shared>) {
+    loop {
+        let next = shared
+            .queue
+            .lock()
+            .expect("should be able to lock outbox")
+            .pop_front();
+        match next {
+            Some(message) => {
+                if sink.send(message).await.is_err() {
+                    break;
+                }
+            }
+            None if shared.closed.load(Ordering::Acquire) => break,
+            None => shared.notify.notified().await,
+        }
+    }
+    shared.closed.store(true, Ordering::Release);
+    sink.close().await.ok();
+}
+
+/// checks the messages a client sends against the size and rate limits
+pub struct Inbound {
+    limits: Limits,
+    stats: Arc<Stats>,
+    /// token bucket, refilled at [`Limits::rate`]
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Inbound {
+    pub fn new(limits: Limits, stats: Arc<Stats>) -> Self {
+        Inbound {
+            limits,
+            stats,
+            tokens: limits.burst,
+            refilled: Instant::now(),
+        }
+    }
+
+    /// whether `message` should be handled, counts the ones that should not
+    pub fn admit(&mut self, message: &Message) -> bool {
+        if message.as_bytes().len() > self.limits.max_message_size {
+            self.stats.oversized.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        let now = Instant::now();
+        let elapsed = (now - self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limits.rate).min(self.limits.burst);
+        self.refilled = now;
+        if self.tokens < 1. {
+            self.stats.rate_limited.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        self.tokens -= 1.;
+        true
+    }
+}
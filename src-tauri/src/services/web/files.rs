@@ -0,0 +1,167 @@
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::UNIX_EPOCH;
+
+use percent_encoding::percent_decode_str;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use warp::filters::BoxedFilter;
+use warp::http::header::*;
+use warp::http::{HeaderMap, HeaderValue, Response, StatusCode};
+use warp::hyper::Body;
+use warp::path::Tail;
+use warp::{Filter, Reply};
+
+use super::assets::{ByteRange, byte_range, etag_matches, header, if_range_matches};
+
+/// user directory with fonts, images and sounds for overlays, served under
+/// `/files`
+#[derive(Default)]
+pub struct MediaDirectory(RwLock<Option<PathBuf>>);
+
+impl MediaDirectory {
+    pub fn get(&self) -> Option<PathBuf> {
+        self.0
+            .read()
+            .expect("should be able to lock media directory")
+            .clone()
+    }
+
+    pub fn set(&self, directory: Option<PathBuf>) {
+        *self
+            .0
+            .write()
+            .expect("should be able to lock media directory") = directory;
+    }
+}
+
+pub fn path(directory: Arc<MediaDirectory>) -> BoxedFilter<(impl Reply,)> {
+    warp::path("files")
+        .and(warp::get())
+        .and(warp::path::tail())
+        .and(warp::header::headers_cloned())
+        .then(move |tail: Tail, headers: HeaderMap| {
+            let root = directory.get();
+            async move {
+                match root {
+                    Some(root) => file_response(&root, tail.as_str(), &headers).await,
+                    None => status(StatusCode::NOT_FOUND),
+                }
+            }
+        })
+        .boxed()
+}
+
+/// maps the url `tail` into `root`, `None` for anything that could leave it
+fn resolve(root: &Path, tail: &str) -> Option<PathBuf> {
+    let decoded = percent_decode_str(tail).decode_utf8().ok()?;
+    let mut path = root.to_path_buf();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return None,
+            // separators, drive prefixes and streams on windows
+            _ if segment.contains(['\\', ':', '\0']) => return None,
+            _ => path.push(segment),
+        }
+    }
+    Some(path)
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .expect("response should be valid")
+}
+
+/// answers with a status instead of rejecting, missing files must not fall
+/// through to the app's index page
+async fn file_response(root: &Path, tail: &str, headers: &HeaderMap) -> Response<Body> {
+    let Some(path) = resolve(root, tail) else {
+        return status(StatusCode::FORBIDDEN);
+    };
+    // symlinks could still lead out of the directory
+    let (Ok(root), Ok(path)) = (
+        tokio::fs::canonicalize(root).await,
+        tokio::fs::canonicalize(&path).await,
+    ) else {
+        return status(StatusCode::NOT_FOUND);
+    };
+    if !path.starts_with(&root) {
+        return status(StatusCode::FORBIDDEN);
+    }
+    let Ok(mut file) = tokio::fs::File::open(&path).await else {
+        return status(StatusCode::NOT_FOUND);
+    };
+    let Ok(metadata) = file.metadata().await else {
+        return status(StatusCode::NOT_FOUND);
+    };
+    if !metadata.is_file() {
+        return status(StatusCode::NOT_FOUND);
+    }
+
+    let len = metadata.len() as usize;
+    let modified = metadata.modified().ok();
+    let etag = format!(
+        "\"{len:x}-{:x}\"",
+        modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_millis())
+            .unwrap_or_default()
+    );
+    let mime_type = mime_guess::from_path(&path).first_or_octet_stream();
+
+    let mut response = Response::builder()
+        .header(ACCEPT_RANGES, HeaderValue::from_static("bytes"))
+        .header(CONTENT_TYPE, mime_type.as_ref())
+        // files get edited while designing overlays, always revalidate
+        .header(CACHE_CONTROL, HeaderValue::from_static("no-cache"))
+        .header(ETAG, etag.as_str());
+    if let Some(modified) = modified {
+        response = response.header(LAST_MODIFIED, httpdate::fmt_http_date(modified));
+    }
+
+    if header(headers, IF_NONE_MATCH).is_some_and(|tags| etag_matches(tags, &etag)) {
+        return response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .expect("response should be valid");
+    }
+
+    let range = header(headers, RANGE)
+        .and_then(|range| byte_range(range, len))
+        .filter(|_| {
+            header(headers, IF_RANGE).is_none_or(|if_range| if_range_matches(if_range, &etag))
+        });
+    let response = match range {
+        Some(ByteRange::Satisfiable(range)) => {
+            if file
+                .seek(SeekFrom::Start(range.start as u64))
+                .await
+                .is_err()
+            {
+                return status(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+            let body = ReaderStream::new(file.take((range.end - range.start) as u64));
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    CONTENT_RANGE,
+                    format!("bytes {}-{}/{len}", range.start, range.end - 1),
+                )
+                .header(CONTENT_LENGTH, range.end - range.start)
+                .body(Body::wrap_stream(body))
+        }
+        Some(ByteRange::Unsatisfiable) => response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(CONTENT_RANGE, format!("bytes */{len}"))
+            .body(Body::empty()),
+        None => response
+            .status(StatusCode::OK)
+            .header(CONTENT_LENGTH, len)
+            .body(Body::wrap_stream(ReaderStream::new(file))),
+    };
+    response.expect("response should be valid")
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use local_ip_address::local_ip;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Serialize;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+use super::access::Listen;
+use super::{PEER_PATH, PUBSUB_PATH};
+
+const CURSES_SERVICE: &str = "_curses._tcp.local.";
+const HTTP_SERVICE: &str = "_http._tcp.local.";
+
+/// a curses server found on the local network
+#[derive(Serialize, Clone, Debug)]
+pub struct DiscoveredServer {
+    pub name: String,
+    pub ip: String,
+    pub port: u16,
+    pub https_port: Option<u16>,
+    pub peer_path: String,
+    pub pubsub_path: String,
+}
+
+/// lowercase letters, digits and dashes naming this machine, usable in a host
+/// name
+fn machine_label() -> String {
+    let name = std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| local_ip().ok().map(|ip| ip.to_string()))
+        .unwrap_or_else(|| "curses".to_string());
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+fn instance_name(port: u16) -> String {
+    format!("curses-{}-{port}", machine_label())
+}
+
+/// advertises the server on `port` while `listen` has it reachable beyond
+/// localhost, with the websocket paths and HTTPS port in the TXT records
+pub async fn advertise(port: u16, mut listen: watch::Receiver<Listen>) {
+    let daemon = match ServiceDaemon::new() {
+        Ok(daemon) => daemon,
+        Err(err) => {
+            warn!("could not start mdns advertisement: '{err}'");
+            return;
+        }
+    };
+    let name = instance_name(port);
+    let host_name = format!("{name}.local.");
+    let mut registered = Vec::new();
+    loop {
+        for fullname in registered.drain(..) {
+            if let Err(err) = daemon.unregister(&fullname) {
+                warn!("could not stop advertising '{fullname}': '{err}'");
+            }
+        }
+
+        let current = listen.borrow_and_update().clone();
+        if !current.ip.is_loopback() {
+            let https_port = current.https.map(|(port, _)| port.to_string());
+            let mut properties = vec![
+                ("txtvers", "1"),
+                ("path", "/"),
+                ("peer_path", PEER_PATH),
+                ("pubsub_path", PUBSUB_PATH),
+            ];
+            if let Some(https_port) = &https_port {
+                properties.push(("https_port", https_port.as_str()));
+            }
+            for service in [CURSES_SERVICE, HTTP_SERVICE] {
+                let info =
+                    match ServiceInfo::new(service, &name, &host_name, "", port, &properties[..]) {
+                        Ok(info) => info.enable_addr_auto(),
+                        Err(err) => {
+                            warn!("could not describe mdns service '{service}': '{err}'");
+                            continue;
+                        }
+                    };
+                let fullname = info.get_fullname().to_string();
+                match daemon.register(info) {
+                    Ok(()) => registered.push(fullname),
+                    Err(err) => warn!("could not advertise '{fullname}': '{err}'"),
+                }
+            }
+            debug!("advertising web server as '{name}'");
+        }
+
+        if listen.changed().await.is_err() {
+            break;
+        }
+    }
+    if let Err(err) = daemon.shutdown() {
+        warn!("could not stop mdns advertisement: '{err}'");
+    }
+}
+
+/// browses the local network for `timeout` and collects the curses servers
+/// besides the one on `own_port` of this machine
+pub async fn discover(own_port: u16, timeout: Duration) -> Result<Vec<DiscoveredServer>, String> {
+    let daemon = ServiceDaemon::new().map_err(|err| err.to_string())?;
+    let events = daemon
+        .browse(CURSES_SERVICE)
+        .map_err(|err| err.to_string())?;
+    let own = format!("{}.{CURSES_SERVICE}", instance_name(own_port));
+
+    let mut servers = HashMap::new();
+    let browse = async {
+        while let Ok(event) = events.recv_async().await {
+            let ServiceEvent::ServiceResolved(info) = event else {
+                continue;
+            };
+            if info.get_fullname() == own {
+                continue;
+            }
+            // prefer IPv4, the web server binds to it
+            let Some(ip) = info
+                .get_addresses()
+                .iter()
+                .min_by_key(|ip| !ip.is_ipv4())
+                .copied()
+            else {
+                continue;
+            };
+            let property = |key: &str, default: &str| {
+                info.get_property_val_str(key)
+                    .unwrap_or(default)
+                    .to_string()
+            };
+            servers.insert(
+                info.get_fullname().to_string(),
+                DiscoveredServer {
+                    name: info
+                        .get_fullname()
+                        .trim_end_matches(CURSES_SERVICE)
+                        .trim_end_matches('.')
+                        .to_string(),
+                    ip: ip.to_string(),
+                    port: info.get_port(),
+                    https_port: info
+                        .get_property_val_str("https_port")
+                        .and_then(|port| port.parse().ok()),
+                    peer_path: property("peer_path", PEER_PATH),
+                    pubsub_path: property("pubsub_path", PUBSUB_PATH),
+                },
+            );
+        }
+    };
+    // browsing never ends on its own, collect what answered in time
+    tokio::time::timeout(timeout, browse).await.ok();
+
+    if let Err(err) = daemon.shutdown() {
+        warn!("could not stop mdns discovery: '{err}'");
+    }
+    Ok(servers.into_values().collect())
+}
@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use warp::filters::BoxedFilter;
+use warp::http::header::CONTENT_TYPE;
+use warp::{Filter, Reply};
+
+use crate::services::metrics::Metrics;
+
+/// content type of the Prometheus text format
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// `GET /metrics` for Prometheus to scrape
+pub fn path(metrics: Arc<Metrics>) -> BoxedFilter<(impl Reply,)> {
+    warp::path!("metrics")
+        .and(warp::get())
+        .map(move || warp::reply::with_header(metrics.render(), CONTENT_TYPE, TEXT_FORMAT))
+        .boxed()
+}
@@ -1,51 +1,198 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::sync::Arc;
+use std::time::Duration;
 
+use access::{Access, AccessOptions, Listen};
+use fanout::{Stats, StatsSnapshot};
+use files::MediaDirectory;
+use futures::FutureExt;
+use futures::future::BoxFuture;
 use local_ip_address::local_ip;
+use mdns::DiscoveredServer;
+use network::NetworkInterface;
+use qr::QrFormat;
 use serde::{Deserialize, Serialize};
 use tauri::async_runtime::Mutex;
 use tauri::plugin::{Builder, TauriPlugin};
-use tauri::{Emitter, Manager, Runtime, State, command};
-use tokio::sync::mpsc;
-use warp::Filter;
+use tauri::{AppHandle, Emitter, Manager, Runtime, State, command};
+use tokio::sync::{mpsc, oneshot, watch};
+use tracing::{debug, error};
+use warp::filters::BoxedFilter;
+use warp::reply::Response;
+use warp::{Filter, Reply};
 
-use super::AppConfiguration;
+use super::metrics::Metrics;
+use super::{AppConfiguration, startup_error};
 
+mod access;
+mod api;
 mod assets;
+mod fanout;
+mod files;
+mod mdns;
+mod metrics;
+mod network;
 mod peer;
 mod pubsub;
+mod qr;
+mod status;
+mod text;
+mod tls;
+
+pub use text::{TextEvent, TextEvents, TextSource};
+
+/// messages buffered between the app and the pubsub websocket
+const APP_CHANNEL_CAPACITY: usize = 64;
+const PEER_PATH: &str = "peer";
+const PUBSUB_PATH: &str = "pubsub";
+/// how long to browse for other servers unless asked otherwise
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// counters of the websocket endpoints
+struct WebStats {
+    pubsub: Arc<Stats>,
+    peer: Arc<Stats>,
+}
+
+#[derive(Serialize)]
+struct WebStatsSnapshot {
+    pubsub: StatsSnapshot,
+    peer: StatsSnapshot,
+}
+
+/// dropped and rejected messages of the websocket endpoints since startup
+#[command]
+fn get_web_stats(stats: State<'_, WebStats>) -> WebStatsSnapshot {
+    WebStatsSnapshot {
+        pubsub: stats.pubsub.snapshot(),
+        peer: stats.peer.snapshot(),
+    }
+}
+
+/// state of the native services and connected clients, also served as
+/// `GET /status`
+#[command]
+fn get_status<R: Runtime>(app: AppHandle<R>) -> status::Status {
+    status::collect(&app)
+}
 
 struct PubSubInput {
     tx: Mutex<mpsc::Sender<String>>,
 }
 
+/// sends `value` to every pubsub peer subscribed to its topic
 #[command]
-async fn pubsub_broadcast(value: String, input: State<'_, PubSubInput>) -> Result<(), String> {
+async fn pubsub_broadcast(
+    value: String,
+    input: State<'_, PubSubInput>,
+    text_events: State<'_, TextEvents>,
+) -> Result<(), String> {
+    text_events.publish(&value);
     let tx = input.tx.lock().await;
     tx.send(value).await.map_err(|e| e.to_string())
 }
 
 #[derive(Serialize)]
 struct WebConfig {
+    /// best guess of the address other devices reach the server on
     pub local_ip: String,
+    /// every address other devices could reach the server on
+    pub interfaces: Vec<NetworkInterface>,
     pub port: String,
     pub peer_path: String,
     pub pubsub_path: String,
+    /// access token other machines need to present, see [`access`]
+    pub token: String,
+    /// port HTTPS is served on, `None` while it is disabled
+    pub https_port: Option<u16>,
+    /// SHA-256 fingerprint of the self signed HTTPS certificate, to compare
+    /// against what the browser shows before trusting it
+    pub tls_fingerprint: Option<String>,
 }
 
 #[command]
-async fn config(config: State<'_, AppConfiguration>) -> Result<WebConfig, String> {
-    let Ok(ip) = local_ip() else {
-        return Err("Error retrieving local IP".to_string());
+async fn config(
+    config: State<'_, AppConfiguration>,
+    access: State<'_, Arc<Access>>,
+) -> Result<WebConfig, String> {
+    let interfaces = network::interfaces();
+    let ip = match interfaces.iter().find(|interface| interface.default) {
+        Some(interface) => interface.ip.clone(),
+        None => local_ip()
+            .map_err(|_| "Error retrieving local IP".to_string())?
+            .to_string(),
     };
     Ok(WebConfig {
-        local_ip: ip.to_string(),
+        local_ip: ip,
+        interfaces,
         port: config.port.to_string(),
-        peer_path: "peer".to_string(),
-        pubsub_path: "pubsub".to_string(),
+        peer_path: PEER_PATH.to_string(),
+        pubsub_path: PUBSUB_PATH.to_string(),
+        token: access.token(),
+        https_port: access.options().https_port,
+        tls_fingerprint: access.fingerprint(),
     })
 }
 
+/// QR code of `url` as a data url, for phones to open a page by scanning it
+#[command]
+fn qr_code(url: String, format: Option<QrFormat>, size: Option<u32>) -> Result<String, String> {
+    qr::data_url(&url, format.unwrap_or_default(), size).map_err(|e| format!("{e:#}"))
+}
+
+/// replaces the access token, returns the new one
+///
+/// links, overlays and phones using the old token need to be set up again
+#[command]
+fn regenerate_token(access: State<'_, Arc<Access>>) -> Result<String, String> {
+    access.regenerate_token().map_err(|e| format!("{e:#}"))
+}
+
+#[command]
+fn get_access_options(access: State<'_, Arc<Access>>) -> AccessOptions {
+    access.options()
+}
+
+/// restricts who may use the web server, rebinds it when switching between
+/// serving on all interfaces and localhost only or when changing HTTPS
+#[command]
+fn set_access_options(
+    options: AccessOptions,
+    access: State<'_, Arc<Access>>,
+) -> Result<(), String> {
+    access.set_options(options).map_err(|e| format!("{e:#}"))
+}
+
+/// other curses servers advertising themselves on the local network
+#[command]
+async fn discover_servers(
+    timeout_ms: Option<u64>,
+    config: State<'_, AppConfiguration>,
+) -> Result<Vec<DiscoveredServer>, String> {
+    let timeout = timeout_ms.map_or(DISCOVERY_TIMEOUT, Duration::from_millis);
+    mdns::discover(config.port, timeout).await
+}
+
+#[command]
+fn get_media_directory(directory: State<'_, Arc<MediaDirectory>>) -> Option<PathBuf> {
+    directory.get()
+}
+
+/// changes the directory served under `/files`, `None` stops serving files
+#[command]
+fn set_media_directory(
+    path: Option<PathBuf>,
+    directory: State<'_, Arc<MediaDirectory>>,
+) -> Result<(), String> {
+    if let Some(path) = path.as_ref().filter(|path| !path.is_dir()) {
+        return Err(format!("'{}' is not a directory", path.display()));
+    }
+    directory.set(path);
+    Ok(())
+}
+
 #[cfg(windows)]
 fn try_open_browser(browser: &String, url: &String) -> Result<bool, String> {
     Ok(Command::new("cmd")
@@ -93,36 +240,171 @@ fn open_browser(data: OpenBrowserCommand) -> Result<(), String> {
     Err("Could not find browser executable".to_string())
 }
 
+enum Scheme {
+    Http(u16),
+    /// on the port from the [`Listen`] options, if any
+    Https,
+}
+
+impl std::fmt::Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scheme::Http(_) => write!(f, "http"),
+            Scheme::Https => write!(f, "https"),
+        }
+    }
+}
+
+/// serves `routes` where `listen` says, rebinding whenever that changes
+///
+/// `bound_tx` gets whether the first bind worked
+async fn serve(
+    routes: BoxedFilter<(Response,)>,
+    mut listen: watch::Receiver<Listen>,
+    scheme: Scheme,
+    mut bound_tx: Option<oneshot::Sender<Result<(), String>>>,
+) {
+    loop {
+        let current = listen.borrow_and_update().clone();
+        let mut rebind = listen.clone();
+        let shutdown = async move {
+            rebind.changed().await.ok();
+        };
+        let server = warp::serve(routes.clone());
+        let bound: Result<(SocketAddr, BoxFuture<'static, ()>), _> = match (&scheme, current.https)
+        {
+            (Scheme::Http(port), _) => server
+                .try_bind_with_graceful_shutdown((current.ip, *port), shutdown)
+                .map(|(addr, server)| (addr, server.boxed())),
+            (Scheme::Https, Some((port, certificate))) => server
+                .tls()
+                .cert(&certificate.cert_pem)
+                .key(&certificate.key_pem)
+                .try_bind_with_graceful_shutdown((current.ip, port), shutdown)
+                .map(|(addr, server)| (addr, server.boxed())),
+            (Scheme::Https, None) => {
+                if listen.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+        if let Some(bound_tx) = bound_tx.take() {
+            let _ = bound_tx.send(bound.as_ref().map(|_| ()).map_err(ToString::to_string));
+        }
+        match bound {
+            Ok((addr, server)) => {
+                debug!("web server listening on {addr}");
+                server.await;
+            }
+            Err(err) => {
+                error!(
+                    "could not start {scheme} web server on {}: '{err}'",
+                    current.ip
+                );
+                if listen.changed().await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
 pub fn init<R: Runtime>() -> TauriPlugin<R> {
-    let (pubsub_input_tx, pubsub_input_rx) = mpsc::channel::<String>(1); // to pubsub
-    let (pubsub_output_tx, mut pubsub_output_rx) = mpsc::channel::<String>(1); // to js
+    let (pubsub_input_tx, pubsub_input_rx) = mpsc::channel::<String>(APP_CHANNEL_CAPACITY); // to pubsub
+    let (pubsub_output_tx, mut pubsub_output_rx) = mpsc::channel::<String>(APP_CHANNEL_CAPACITY); // to js
     Builder::new("web")
         .invoke_handler(tauri::generate_handler![
             open_browser,
             pubsub_broadcast,
-            config
+            config,
+            get_access_options,
+            set_access_options,
+            regenerate_token,
+            get_web_stats,
+            get_media_directory,
+            set_media_directory,
+            discover_servers,
+            qr_code,
+            get_status
         ])
         .setup(|app, _api| {
             app.manage(PubSubInput {
                 tx: Mutex::new(pubsub_input_tx),
             });
+            app.manage(TextEvents::default());
 
             let app_port = app.state::<AppConfiguration>().port;
+            let access = Arc::new(Access::load_or_generate(
+                app_port,
+                &app.path().app_data_dir()?,
+            ));
+            app.manage(access.clone());
+
+            let stats = WebStats {
+                pubsub: Arc::default(),
+                peer: Arc::default(),
+            };
+            let pubsub_stats = stats.pubsub.clone();
+            let peer_stats = stats.peer.clone();
+            app.manage(stats);
+
+            let media_directory = Arc::new(MediaDirectory::default());
+            app.manage(media_directory.clone());
 
+            let (bound_tx, bound_rx) = oneshot::channel();
             let a = Arc::new(app.asset_resolver());
+            let api_app = app.clone();
+            let status_app = app.clone();
+            let app_metrics = app.state::<Arc<Metrics>>().inner().clone();
             tauri::async_runtime::spawn(async move {
-                let routes = warp::path!("ping")
-                    .map(|| "pong".to_string())
-                    .or(peer::path())
-                    .or(pubsub::path(pubsub_input_rx, pubsub_output_tx))
-                    .or(assets::path(a));
+                let guarded = access::guard(access.clone())
+                    .and(
+                        warp::path!("ping")
+                            .map(|| "pong".to_string())
+                            .or(status::path(status_app))
+                            .or(metrics::path(app_metrics.clone()))
+                            .or(peer::path(peer_stats))
+                            .or(api::path(api_app, pubsub_output_tx.clone()))
+                            .or(pubsub::path(
+                                pubsub_input_rx,
+                                pubsub_output_tx,
+                                pubsub_stats,
+                                app_metrics,
+                            ))
+                            .or(files::path(media_directory))
+                            .or(assets::path(a)),
+                    )
+                    .map(access::finish);
+                let routes = access::preflight(access.clone())
+                    .or(guarded)
+                    .unify()
+                    .recover(access::handle_rejection)
+                    .map(Reply::into_response)
+                    .boxed();
 
-                loop {
-                    warp::serve(routes.clone())
-                        .run(([0, 0, 0, 0], app_port))
-                        .await
-                }
+                tauri::async_runtime::spawn(mdns::advertise(app_port, access.listen()));
+                tauri::async_runtime::spawn(serve(
+                    routes.clone(),
+                    access.listen(),
+                    Scheme::Https,
+                    None,
+                ));
+                serve(
+                    routes,
+                    access.listen(),
+                    Scheme::Http(app_port),
+                    Some(bound_tx),
+                )
+                .await;
             });
+            // the port was free when the app started, but something else may
+            // have taken it since
+            if let Ok(Err(err)) = tauri::async_runtime::block_on(bound_rx) {
+                startup_error(&format!(
+                    "Could not start the web server on port {app_port}: {err}"
+                ));
+            }
             let handle = app.clone();
             tauri::async_runtime::spawn(async move {
                 loop {
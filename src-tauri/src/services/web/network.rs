@@ -0,0 +1,104 @@
+use std::net::IpAddr;
+
+use local_ip_address::{list_afinet_netifas, local_ip};
+use serde::Serialize;
+use tracing::warn;
+
+/// name prefixes of interfaces that belong to VPNs, containers or virtual
+/// machines, other devices rarely reach the server through these
+const VIRTUAL_PREFIXES: &[&str] = &[
+    "docker",
+    "br-",
+    "veth",
+    "virbr",
+    "vmnet",
+    "vboxnet",
+    "vethernet",
+    "tun",
+    "tap",
+    "wg",
+    "utun",
+    "tailscale",
+    "zt",
+    "ham",
+];
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Family {
+    Ipv4,
+    Ipv6,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub ip: String,
+    pub family: Family,
+    /// the address other devices most likely reach the server on, set for
+    /// exactly one interface
+    pub default: bool,
+}
+
+fn usable(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !ip.is_loopback() && !ip.is_unspecified() && !ip.is_link_local(),
+        // link local addresses need a zone, browsers do not take those
+        IpAddr::V6(ip) => {
+            !ip.is_loopback() && !ip.is_unspecified() && (ip.segments()[0] & 0xffc0) != 0xfe80
+        }
+    }
+}
+
+fn is_virtual(name: &str) -> bool {
+    let name = name.to_lowercase();
+    VIRTUAL_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// the addresses of every interface other devices could reach the server on,
+/// IPv4 first
+pub fn interfaces() -> Vec<NetworkInterface> {
+    let mut interfaces = match list_afinet_netifas() {
+        Ok(interfaces) => interfaces,
+        Err(err) => {
+            warn!("could not list network interfaces: '{err}'");
+            Vec::new()
+        }
+    };
+    interfaces.retain(|(_, ip)| usable(ip));
+    interfaces.sort_by_key(|(name, ip)| (ip.is_ipv6(), is_virtual(name)));
+
+    // the interface of the default route, unless that is a VPN or bridge
+    let routed = local_ip().ok().filter(|ip| {
+        interfaces
+            .iter()
+            .any(|(name, other)| other == ip && !is_virtual(name))
+    });
+    let default = routed
+        .and_then(|routed| interfaces.iter().position(|(_, ip)| *ip == routed))
+        .unwrap_or_default();
+
+    interfaces
+        .into_iter()
+        .enumerate()
+        .map(|(index, (name, ip))| NetworkInterface {
+            name,
+            ip: ip.to_string(),
+            family: if ip.is_ipv4() {
+                Family::Ipv4
+            } else {
+                Family::Ipv6
+            },
+            default: index == default,
+        })
+        .collect()
+}
+
+/// whether `ip` belongs to this machine
+pub fn is_local(ip: IpAddr) -> bool {
+    ip.is_loopback()
+        || list_afinet_netifas()
+            .is_ok_and(|interfaces| interfaces.iter().any(|(_, other)| *other == ip))
+}
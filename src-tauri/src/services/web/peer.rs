@@ -1,61 +1,333 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as SerdeValue;
-use tauri::async_runtime::RwLock;
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use serde_json::json;
+use tauri::async_runtime::Mutex;
+use tokio::time::Instant;
+use tracing::debug;
 use warp::filters::BoxedFilter;
 use warp::ws::{Message, WebSocket, Ws};
 use warp::{Filter, Reply};
 
+use super::fanout::{Inbound, Limits, Outbox, Overflow, Stats};
+
 #[derive(Deserialize)]
 pub struct PeerQueryData {
     id: String,
+    /// random per session value of the PeerJS client, lets it reclaim its id
+    /// when reconnecting
+    #[serde(default)]
+    token: String,
+}
+
+/// how long the server waits on peers
+#[derive(Clone, Copy, Debug)]
+pub struct Timeouts {
+    /// peers sending nothing for this long, not even a heartbeat, get evicted
+    pub alive: Duration,
+    /// messages queued for a peer that does not connect within this get
+    /// answered with `EXPIRE`
+    pub expire: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        // the PeerJS client sends a heartbeat every 5 seconds
+        Timeouts {
+            alive: Duration::from_secs(60),
+            expire: Duration::from_secs(5),
+        }
+    }
+}
+
+/// signaling messages must not get lost, so peers that fall behind get
+/// disconnected and have to reconnect
+const LIMITS: Limits = Limits {
+    queue: 64,
+    overflow: Overflow::Disconnect,
+    max_message_size: 64 * 1024,
+    rate: 50.,
+    burst: 100.,
+};
+
+struct Client {
+    token: String,
+    outbox: Outbox,
+    /// tells apart the sockets of a client that reconnected with its id
+    connection: u64,
+    last_seen: Instant,
+    /// peers this client exchanged messages with, they get a `LEAVE` when it
+    /// gets evicted
+    contacts: HashSet<String>,
+}
+
+struct Queued {
+    message: PeerMessage,
+    since: Instant,
+}
+
+/// the connected peers and the messages waiting for peers to connect
+#[derive(Default)]
+struct Realm {
+    clients: HashMap<String, Client>,
+    queues: HashMap<String, VecDeque<Queued>>,
+    connections: u64,
+}
+
+type Peers = Arc<Mutex<Realm>>;
+
+impl Realm {
+    /// registers the socket of `id` and delivers the messages queued for it,
+    /// hands `outbox` back when the id is taken by a client with another token
+    fn connect(
+        &mut self,
+        id: &str,
+        token: &str,
+        outbox: Outbox,
+        now: Instant,
+    ) -> Result<u64, Outbox> {
+        self.connections += 1;
+        let connection = self.connections;
+        match self.clients.get_mut(id) {
+            Some(client) if client.token != token => return Err(outbox),
+            Some(client) => {
+                client.outbox = outbox;
+                client.connection = connection;
+                client.last_seen = now;
+            }
+            None => {
+                self.clients.insert(
+                    id.to_string(),
+                    Client {
+                        token: token.to_string(),
+                        outbox,
+                        connection,
+                        last_seen: now,
+                        contacts: HashSet::new(),
+                    },
+                );
+            }
+        }
+        self.send(id, &PeerMessage::from(PeerMessageType::Open));
+        for queued in self.queues.remove(id).unwrap_or_default() {
+            self.link(&queued.message.src, id);
+            self.send(id, &queued.message);
+        }
+        Ok(connection)
+    }
+
+    /// forgets `id`, unless it reconnected with another socket in the meantime
+    fn disconnect(&mut self, id: &str, connection: u64) {
+        if self
+            .clients
+            .get(id)
+            .is_some_and(|client| client.connection == connection)
+        {
+            self.remove(id);
+        }
+    }
+
+    /// notes that `id` is still alive, `false` when this socket of it got
+    /// evicted or replaced
+    fn seen(&mut self, id: &str, connection: u64, now: Instant) -> bool {
+        match self.clients.get_mut(id) {
+            Some(client) if client.connection == connection => {
+                client.last_seen = now;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// passes a message of `src` on to its destination, queueing it when the
+    /// destination has not connected yet
+    fn relay(&mut self, src: &str, mut message: PeerMessage, now: Instant) {
+        message.src = src.to_string();
+        if message.dst.is_empty() {
+            // a `LEAVE` without destination unregisters the sender
+            if message.t == PeerMessageType::Leave {
+                self.remove(src);
+            }
+            return;
+        }
+        if self.clients.contains_key(&message.dst) {
+            self.link(src, &message.dst);
+            self.send(&message.dst, &message);
+        } else if !matches!(message.t, PeerMessageType::Leave | PeerMessageType::Expire) {
+            self.queues
+                .entry(message.dst.clone())
+                .or_default()
+                .push_back(Queued {
+                    message,
+                    since: now,
+                });
+        }
+    }
+
+    /// evicts peers that stopped sending heartbeats, telling their contacts,
+    /// and expires queued messages
+    fn sweep(&mut self, timeouts: Timeouts, now: Instant) {
+        let dead: Vec<String> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| now - client.last_seen >= timeouts.alive)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in dead {
+            debug!("evicting unresponsive peer '{id}'");
+            // dropping the client closes its socket
+            let Some(client) = self.remove(&id) else {
+                continue;
+            };
+            for contact in client.contacts {
+                self.send(
+                    &contact,
+                    &PeerMessage {
+                        t: PeerMessageType::Leave,
+                        src: id.clone(),
+                        dst: contact.clone(),
+                        payload: SerdeValue::Null,
+                    },
+                );
+            }
+        }
+
+        let mut expired = Vec::new();
+        for queue in self.queues.values_mut() {
+            while queue
+                .front()
+                .is_some_and(|queued| now - queued.since >= timeouts.expire)
+            {
+                expired.extend(queue.pop_front());
+            }
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+        for Queued { message, .. } in expired {
+            self.send(
+                &message.src,
+                &PeerMessage {
+                    t: PeerMessageType::Expire,
+                    src: message.dst,
+                    dst: message.src.clone(),
+                    payload: SerdeValue::Null,
+                },
+            );
+        }
+    }
+
+    fn send(&self, id: &str, message: &PeerMessage) {
+        if let Some(client) = self.clients.get(id) {
+            client.outbox.push(message.into());
+        }
+    }
+
+    fn link(&mut self, a: &str, b: &str) {
+        if let Some(client) = self.clients.get_mut(a) {
+            client.contacts.insert(b.to_string());
+        }
+        if let Some(client) = self.clients.get_mut(b) {
+            client.contacts.insert(a.to_string());
+        }
+    }
+
+    fn remove(&mut self, id: &str) -> Option<Client> {
+        let client = self.clients.remove(id)?;
+        for contact in &client.contacts {
+            if let Some(other) = self.clients.get_mut(contact) {
+                other.contacts.remove(id);
+            }
+        }
+        Some(client)
+    }
 }
 
-pub type Peers = Arc<RwLock<HashMap<String, mpsc::UnboundedSender<Result<Message, warp::Error>>>>>;
+pub fn path(stats: Arc<Stats>) -> BoxedFilter<(impl Reply,)> {
+    path_with(Timeouts::default(), stats)
+}
 
-pub fn path() -> BoxedFilter<(impl Reply,)> {
+fn path_with(timeouts: Timeouts, stats: Arc<Stats>) -> BoxedFilter<(impl Reply,)> {
     let peers = Peers::default();
+
+    let sweep_peers = peers.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(timeouts.alive.min(timeouts.expire) / 5);
+        loop {
+            interval.tick().await;
+            sweep_peers.lock().await.sweep(timeouts, Instant::now());
+        }
+    });
+
     let peers = warp::any().map(move || peers.clone());
+    let stats = warp::any().map(move || stats.clone());
 
-    warp::path("peer")
+    warp::path(super::PEER_PATH)
         .and(warp::ws())
         .and(peers)
+        .and(stats)
         .and(warp::query::<PeerQueryData>())
-        .map(|ws: Ws, peers, q| ws.on_upgrade(move |socket| peer_handler(socket, peers, q)))
+        .map(|ws: Ws, peers, stats, q| {
+            // larger frames are refused before they are buffered, `Inbound`
+            // only gets to count what fits
+            ws.max_frame_size(LIMITS.max_message_size)
+                .max_message_size(LIMITS.max_message_size)
+                .on_upgrade(move |socket| peer_handler(socket, peers, stats, q))
+        })
         .boxed()
 }
 
-pub async fn peer_handler(ws: WebSocket, peers: Peers, query: PeerQueryData) {
+async fn peer_handler(ws: WebSocket, peers: Peers, stats: Arc<Stats>, query: PeerQueryData) {
     let (peer_tx, mut peer_rx) = ws.split();
+    let outbox = Outbox::spawn(peer_tx, LIMITS, stats.clone());
+    let mut inbound = Inbound::new(LIMITS, stats);
 
-    let (tx, rx) = mpsc::unbounded_channel();
-    let rx = UnboundedReceiverStream::new(rx);
-    tauri::async_runtime::spawn(rx.forward(peer_tx));
-
-    if peers.read().await.contains_key(&query.id) {
-        println!("already registered");
-        return;
-    }
-
-    tx.send(Ok(PeerMessageType::Open.into())).unwrap();
-
-    peers.write().await.insert(query.id.clone(), tx);
+    let connection =
+        match peers
+            .lock()
+            .await
+            .connect(&query.id, &query.token, outbox, Instant::now())
+        {
+            Ok(connection) => connection,
+            Err(outbox) => {
+                debug!("peer id '{}' is already taken", query.id);
+                let taken = PeerMessage {
+                    payload: json!({ "msg": "ID is taken" }),
+                    ..PeerMessage::from(PeerMessageType::IdTaken)
+                };
+                // dropping the outbox afterwards closes the socket
+                outbox.push((&taken).into());
+                return;
+            }
+        };
 
     while let Some(result) = peer_rx.next().await {
         let Ok(msg) = result else {
             break;
         };
-        handle_message(&query.id, msg, &peers).await;
+        let now = Instant::now();
+        let mut realm = peers.lock().await;
+        if !realm.seen(&query.id, connection, now) {
+            break;
+        }
+        if !inbound.admit(&msg) {
+            continue;
+        }
+        let Ok(msg_str) = msg.to_str() else { continue };
+        let Ok(message) = serde_json::from_str::<PeerMessage>(msg_str) else {
+            continue;
+        };
+        // heartbeats only keep the peer alive
+        if message.t != PeerMessageType::Heartbeat {
+            realm.relay(&query.id, message, now);
+        }
     }
-    peers.write().await.remove(&query.id);
+    peers.lock().await.disconnect(&query.id, connection);
 }
 
-#[derive(Default, Serialize, Deserialize, PartialEq)]
+#[derive(Default, Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
 #[serde(rename_all = "UPPERCASE")]
 enum PeerMessageType {
     Open, // socket ready
@@ -65,67 +337,289 @@ enum PeerMessageType {
     Answer,
     Expire, // host not found
     Heartbeat,
-    #[serde(rename(serialize = "ID_TAKEN", deserialize = "IDTAKEN"))]
-    IDTaken,
+    #[serde(rename = "ID-TAKEN")]
+    IdTaken,
     #[default]
     Error,
 }
 
-impl From<PeerMessageType> for Message {
-    fn from(val: PeerMessageType) -> Self {
-        Message::text(serde_json::to_string(&PeerMessageShort { t: val }).unwrap())
+impl From<PeerMessageType> for PeerMessage {
+    fn from(t: PeerMessageType) -> Self {
+        PeerMessage {
+            t,
+            src: String::new(),
+            dst: String::new(),
+            payload: SerdeValue::Null,
+        }
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq)]
+impl From<&PeerMessage> for Message {
+    fn from(val: &PeerMessage) -> Self {
+        Message::text(serde_json::to_string(val).unwrap())
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 struct PeerMessage {
-    #[serde(rename(serialize = "type", deserialize = "type"))]
-    #[serde(default)]
+    #[serde(rename = "type", default)]
     pub t: PeerMessageType,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub src: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub dst: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "SerdeValue::is_null")]
     pub payload: SerdeValue,
 }
 
-#[derive(Serialize, Deserialize, PartialEq)]
-struct PeerMessageShort {
-    #[serde(rename(serialize = "type", deserialize = "type"))]
-    #[serde(default)]
-    pub t: PeerMessageType,
-}
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use serde_json::{Value, json};
+    use tokio::time::{sleep, timeout};
+    use warp::Reply;
+    use warp::filters::BoxedFilter;
+    use warp::test::WsClient;
 
-async fn handle_message(peer_id: &String, msg: Message, users: &Peers) {
-    let Ok(msg_str) = msg.to_str() else { return };
-    let Ok(mut msg) = serde_json::from_str::<PeerMessage>(msg_str) else {
-        return;
+    use super::{Timeouts, path_with};
+
+    const TIMEOUTS: Timeouts = Timeouts {
+        alive: Duration::from_millis(400),
+        expire: Duration::from_millis(200),
     };
 
-    let users = users.read().await;
+    async fn connect(
+        server: &BoxedFilter<(impl Reply + Send + 'static,)>,
+        id: &str,
+        token: &str,
+    ) -> WsClient {
+        warp::test::ws()
+            .path(&format!("/peer?id={id}&token={token}"))
+            .handshake(server.clone())
+            .await
+            .expect("handshake should succeed")
+    }
 
-    if msg.t == PeerMessageType::Offer && !users.contains_key(msg.dst.as_str()) {
-        let Some(peer_tx) = users.get(peer_id) else {
-            return;
-        };
-        let Ok(msg_str) = serde_json::to_string(&PeerMessage {
-            t: PeerMessageType::Expire,
-            src: msg.dst,
-            dst: peer_id.clone(),
-            payload: msg.payload,
-        }) else {
-            return;
-        };
-        peer_tx.send(Ok(Message::text(msg_str))).unwrap();
-    } else if !msg.dst.is_empty() && users.contains_key(&msg.dst) {
-        msg.src = peer_id.clone();
-        let Some(peer_tx) = users.get(&msg.dst) else {
-            return;
-        };
-        let Ok(msg_str) = serde_json::to_string(&msg) else {
-            return;
-        };
-        peer_tx.send(Ok(Message::text(msg_str))).unwrap();
+    /// connects and consumes the `OPEN` message
+    async fn open(
+        server: &BoxedFilter<(impl Reply + Send + 'static,)>,
+        id: &str,
+        token: &str,
+    ) -> WsClient {
+        let mut client = connect(server, id, token).await;
+        assert_eq!(recv(&mut client).await, json!({ "type": "OPEN" }));
+        client
+    }
+
+    async fn recv(client: &mut WsClient) -> Value {
+        let message = timeout(Duration::from_secs(2), client.recv())
+            .await
+            .expect("should receive a message in time")
+            .expect("socket should be open");
+        serde_json::from_str(message.to_str().expect("should be a text message"))
+            .expect("should be json")
+    }
+
+    async fn send(client: &mut WsClient, message: Value) {
+        client.send_text(message.to_string()).await;
+    }
+
+    async fn assert_silent(client: &mut WsClient) {
+        let received = timeout(Duration::from_millis(100), client.recv()).await;
+        assert!(received.is_err(), "unexpected message {received:?}");
+    }
+
+    async fn assert_closed(client: &mut WsClient) {
+        timeout(Duration::from_secs(2), client.recv_closed())
+            .await
+            .expect("socket should close in time")
+            .expect("socket should be closed");
+    }
+
+    /// keeps `client` alive for `duration`
+    async fn heartbeat(client: &mut WsClient, duration: Duration) {
+        let interval = TIMEOUTS.alive / 4;
+        let mut waited = Duration::ZERO;
+        while waited < duration {
+            send(client, json!({ "type": "HEARTBEAT" })).await;
+            sleep(interval).await;
+            waited += interval;
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn opens_new_peer() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        open(&server, "a", "token").await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_taken_id() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        let _a = open(&server, "a", "token").await;
+
+        let mut intruder = connect(&server, "a", "other").await;
+        assert_eq!(
+            recv(&mut intruder).await,
+            json!({ "type": "ID-TAKEN", "payload": { "msg": "ID is taken" } })
+        );
+        assert_closed(&mut intruder).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reconnect_with_token_takes_over() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        let _old = open(&server, "a", "token").await;
+        let mut new = open(&server, "a", "token").await;
+        let mut b = open(&server, "b", "token").await;
+
+        send(&mut b, json!({ "type": "OFFER", "dst": "a", "payload": 1 })).await;
+        assert_eq!(
+            recv(&mut new).await,
+            json!({ "type": "OFFER", "src": "b", "dst": "a", "payload": 1 })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn relays_with_sender_as_source() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        let mut a = open(&server, "a", "token").await;
+        let mut b = open(&server, "b", "token").await;
+
+        // a forged source gets replaced
+        send(
+            &mut a,
+            json!({ "type": "OFFER", "src": "c", "dst": "b", "payload": { "sdp": "x" } }),
+        )
+        .await;
+        assert_eq!(
+            recv(&mut b).await,
+            json!({ "type": "OFFER", "src": "a", "dst": "b", "payload": { "sdp": "x" } })
+        );
+
+        send(
+            &mut b,
+            json!({ "type": "ANSWER", "dst": "a", "payload": 2 }),
+        )
+        .await;
+        assert_eq!(
+            recv(&mut a).await,
+            json!({ "type": "ANSWER", "src": "b", "dst": "a", "payload": 2 })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn queues_for_peers_not_connected_yet() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        let mut a = open(&server, "a", "token").await;
+
+        send(&mut a, json!({ "type": "OFFER", "dst": "b", "payload": 1 })).await;
+        send(
+            &mut a,
+            json!({ "type": "CANDIDATE", "dst": "b", "payload": 2 }),
+        )
+        .await;
+        sleep(TIMEOUTS.expire / 4).await;
+
+        let mut b = open(&server, "b", "token").await;
+        assert_eq!(
+            recv(&mut b).await,
+            json!({ "type": "OFFER", "src": "a", "dst": "b", "payload": 1 })
+        );
+        assert_eq!(
+            recv(&mut b).await,
+            json!({ "type": "CANDIDATE", "src": "a", "dst": "b", "payload": 2 })
+        );
+        assert_silent(&mut a).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn expires_undelivered_messages() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        let mut a = open(&server, "a", "token").await;
+
+        send(&mut a, json!({ "type": "OFFER", "dst": "b", "payload": 1 })).await;
+        assert_eq!(
+            recv(&mut a).await,
+            json!({ "type": "EXPIRE", "src": "b", "dst": "a" })
+        );
+
+        // expired messages are not delivered anymore
+        let mut b = open(&server, "b", "token").await;
+        assert_silent(&mut b).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn heartbeats_keep_peers_alive() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        let mut a = open(&server, "a", "token").await;
+
+        heartbeat(&mut a, TIMEOUTS.alive * 2).await;
+
+        let mut b = open(&server, "b", "token").await;
+        send(&mut b, json!({ "type": "OFFER", "dst": "a", "payload": 1 })).await;
+        assert_eq!(
+            recv(&mut a).await,
+            json!({ "type": "OFFER", "src": "b", "dst": "a", "payload": 1 })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn evicts_silent_peers_and_tells_contacts() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        let mut a = open(&server, "a", "token").await;
+        let mut b = open(&server, "b", "token").await;
+
+        send(&mut a, json!({ "type": "OFFER", "dst": "b", "payload": 1 })).await;
+        recv(&mut b).await;
+
+        heartbeat(&mut b, TIMEOUTS.alive * 2).await;
+        assert_eq!(
+            recv(&mut b).await,
+            json!({ "type": "LEAVE", "src": "a", "dst": "b" })
+        );
+        assert_closed(&mut a).await;
+
+        // the id is free again
+        open(&server, "a", "other").await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn relays_leave() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        let mut a = open(&server, "a", "token").await;
+        let mut b = open(&server, "b", "token").await;
+
+        send(&mut a, json!({ "type": "LEAVE", "dst": "b" })).await;
+        assert_eq!(
+            recv(&mut b).await,
+            json!({ "type": "LEAVE", "src": "a", "dst": "b" })
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn does_not_queue_leave() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        let mut a = open(&server, "a", "token").await;
+
+        send(&mut a, json!({ "type": "LEAVE", "dst": "b" })).await;
+        sleep(TIMEOUTS.expire / 4).await;
+
+        let mut b = open(&server, "b", "token").await;
+        assert_silent(&mut b).await;
+        assert_silent(&mut a).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn leave_without_destination_frees_id() {
+        let server = path_with(TIMEOUTS, Arc::default());
+        let mut a = open(&server, "a", "token").await;
+
+        send(&mut a, json!({ "type": "LEAVE" })).await;
+        sleep(TIMEOUTS.expire / 4).await;
+
+        open(&server, "a", "other").await;
     }
 }
@@ -1,51 +1,231 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 
 use futures::StreamExt;
 use serde::Deserialize;
+use serde_json::Value as SerdeValue;
 use tauri::async_runtime::RwLock;
 use tokio::sync::mpsc;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::warn;
 use warp::filters::BoxedFilter;
 use warp::ws::{Message, WebSocket, Ws};
 use warp::{Filter, Reply};
 
+use super::fanout::{Inbound, Limits, Outbox, Overflow, Stats};
+use crate::services::metrics::Metrics;
+
 #[derive(Deserialize)]
 pub struct PeerQueryData {
     id: String,
+    /// comma separated topics to subscribe to right away
+    #[serde(default)]
+    topics: Option<String>,
+    /// replay the messages after this sequence number, for clients picking up
+    /// where they left off
+    #[serde(default)]
+    since: Option<u64>,
+    /// replay the latest message of every topic, for clients starting blank
+    #[serde(default)]
+    latest: bool,
+}
+
+/// how many messages are kept for replaying
+const HISTORY_LIMIT: usize = 256;
+
+/// overlays only care about the latest state, so slow ones lose old messages
+/// instead of getting disconnected
+const LIMITS: Limits = Limits {
+    queue: HISTORY_LIMIT,
+    overflow: Overflow::DropOldest,
+    max_message_size: 64 * 1024,
+    rate: 50.,
+    burst: 100.,
+};
+
+/// policy violation, sent when a client connects with an id that is in use
+const ID_TAKEN_CLOSE_CODE: u16 = 1008;
+
+/// frames a peer sends to change its subscriptions, these are not relayed
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ControlFrame {
+    Subscribe { topics: Vec<String> },
+    Unsubscribe { topics: Vec<String> },
+}
+
+/// the part of a `{topic, data}` message needed for routing it
+#[derive(Deserialize)]
+struct Routing {
+    topic: String,
+}
+
+pub struct Peer {
+    outbox: Outbox,
+    /// `None` until the peer subscribes to something, it receives every
+    /// message until then
+    topics: Option<HashSet<String>>,
+}
+
+impl Peer {
+    /// whether a message with `topic` should be sent to this peer
+    ///
+    /// subscriptions are hierarchical like the frontend pubsub, `text`
+    /// includes `text.stt`
+    fn wants(&self, topic: Option<&str>) -> bool {
+        let Some(topics) = &self.topics else {
+            return true;
+        };
+        let Some(topic) = topic else {
+            return false;
+        };
+        topics.iter().any(|subscription| {
+            topic
+                .strip_prefix(subscription.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+    }
+
+    fn apply(&mut self, frame: ControlFrame) {
+        match frame {
+            ControlFrame::Subscribe { topics } => {
+                self.topics.get_or_insert_default().extend(topics);
+            }
+            ControlFrame::Unsubscribe { topics } => {
+                if let Some(subscribed) = &mut self.topics {
+                    for topic in topics {
+                        subscribed.remove(&topic);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Entry {
+    seq: u64,
+    topic: Option<String>,
+    /// the message as sent to peers, with its sequence number
+    text: String,
+}
+
+/// the most recent messages, numbered in the order they were relayed
+#[derive(Default)]
+struct History {
+    last_seq: u64,
+    entries: VecDeque<Entry>,
 }
 
-pub type Peers = Arc<RwLock<HashMap<String, mpsc::UnboundedSender<Result<Message, warp::Error>>>>>;
+impl History {
+    /// numbers `message` and keeps it, returns what to send to peers
+    ///
+    /// `{topic, data}` messages get the number as `seq` field, other messages
+    /// are kept as they are
+    fn record(&mut self, message: &str) -> &Entry {
+        self.last_seq += 1;
+        let seq = self.last_seq;
+        let text = match serde_json::from_str::<SerdeValue>(message) {
+            Ok(SerdeValue::Object(mut object)) => {
+                object.insert("seq".to_string(), seq.into());
+                SerdeValue::Object(object).to_string()
+            }
+            _ => message.to_string(),
+        };
+        if self.entries.len() >= HISTORY_LIMIT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry {
+            seq,
+            topic: topic_of(message),
+            text,
+        });
+        self.entries.back().expect("entry was just pushed")
+    }
+
+    /// messages after `seq`, everything still kept when `seq` is too old
+    fn since(&self, seq: u64) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(move |entry| entry.seq > seq)
+    }
+
+    /// the latest message of every topic, in their original order
+    fn latest(&self) -> Vec<&Entry> {
+        let mut seen = HashSet::new();
+        let mut latest: Vec<&Entry> = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| seen.insert(entry.topic.as_deref()))
+            .collect();
+        latest.reverse();
+        latest
+    }
+}
+
+pub struct Hub {
+    peers: HashMap<String, Peer>,
+    history: History,
+    metrics: Arc<Metrics>,
+}
+
+impl Hub {
+    /// records `message` and sends it to every subscribed peer but `sender`
+    fn broadcast(&mut self, message: &str, sender: Option<&str>) {
+        let entry = self.history.record(message);
+        let mut recipients = 0;
+        for (id, peer) in &self.peers {
+            if sender != Some(id.as_str()) && peer.wants(entry.topic.as_deref()) {
+                peer.outbox.push(Message::text(entry.text.as_str()));
+                recipients += 1;
+            }
+        }
+        self.metrics.pubsub_fanout.observe(recipients as f64);
+    }
+}
+
+pub type Peers = Arc<RwLock<Hub>>;
+
+fn topic_of(message: &str) -> Option<String> {
+    serde_json::from_str::<Routing>(message)
+        .ok()
+        .map(|routing| routing.topic)
+}
 
 pub fn path(
     mut input: mpsc::Receiver<String>,
     output: mpsc::Sender<String>,
+    stats: Arc<Stats>,
+    metrics: Arc<Metrics>,
 ) -> BoxedFilter<(impl Reply,)> {
-    let peers = Peers::default();
+    let peers = Arc::new(RwLock::new(Hub {
+        peers: HashMap::new(),
+        history: History::default(),
+        metrics,
+    }));
 
     let input_peers = peers.clone();
     tauri::async_runtime::spawn(async move {
         loop {
             if let Some(input) = input.recv().await {
-                let p = input_peers.read().await;
-                let str = input.as_str();
-                for peer in p.values() {
-                    peer.send(Ok(Message::text(str))).ok();
-                }
+                input_peers.write().await.broadcast(&input, None);
             }
         }
     });
 
     let peers = warp::any().map(move || peers.clone());
     let output = warp::any().map(move || output.clone());
+    let stats = warp::any().map(move || stats.clone());
 
-    warp::path("pubsub")
+    warp::path(super::PUBSUB_PATH)
         .and(warp::ws())
         .and(peers)
         .and(output)
+        .and(stats)
         .and(warp::query::<PeerQueryData>())
-        .map(|ws: Ws, peers, output, q| {
-            ws.on_upgrade(move |socket| peer_handler(socket, peers, output, q))
+        .map(|ws: Ws, peers, output, stats, q| {
+            // larger frames are refused before they are buffered, `Inbound`
+            // only gets to count what fits
+            ws.max_frame_size(LIMITS.max_message_size)
+                .max_message_size(LIMITS.max_message_size)
+                .on_upgrade(move |socket| peer_handler(socket, peers, output, stats, q))
         })
         .boxed()
 }
@@ -54,34 +234,69 @@ pub async fn peer_handler(
     ws: WebSocket,
     peers: Peers,
     output: mpsc::Sender<String>,
+    stats: Arc<Stats>,
     query: PeerQueryData,
 ) {
     let (peer_tx, mut peer_rx) = ws.split();
+    let outbox = Outbox::spawn(peer_tx, LIMITS, stats.clone());
+    let mut inbound = Inbound::new(LIMITS, stats.clone());
 
-    let (tx, rx) = mpsc::unbounded_channel();
-    let rx = UnboundedReceiverStream::new(rx);
-    tauri::async_runtime::spawn(rx.forward(peer_tx));
+    let topics = query.topics.map(|topics| {
+        topics
+            .split(',')
+            .map(str::trim)
+            .filter(|topic| !topic.is_empty())
+            .map(ToString::to_string)
+            .collect()
+    });
+    let peer = Peer { outbox, topics };
 
-    if peers.read().await.contains_key(&query.id) {
-        println!("already registered");
-        return;
+    {
+        let mut hub = peers.write().await;
+        if hub.peers.contains_key(&query.id) {
+            warn!("pubsub id '{}' is already registered", query.id);
+            // dropping the outbox afterwards closes the socket
+            peer.outbox.push(Message::close_with(
+                ID_TAKEN_CLOSE_CODE,
+                "id is already registered",
+            ));
+            return;
+        }
+        // replay while holding the lock, so nothing gets lost or sent twice
+        // between the replay and the live messages
+        let replay: Vec<&Entry> = match (query.since, query.latest) {
+            (Some(seq), _) => hub.history.since(seq).collect(),
+            (None, true) => hub.history.latest(),
+            (None, false) => Vec::new(),
+        };
+        for entry in replay {
+            if peer.wants(entry.topic.as_deref()) {
+                peer.outbox.push(Message::text(entry.text.as_str()));
+            }
+        }
+        hub.peers.insert(query.id.clone(), peer);
     }
 
-    peers.write().await.insert(query.id.clone(), tx);
-
     while let Some(result) = peer_rx.next().await {
         let Ok(msg) = result else {
             break;
         };
         let Ok(msg_str) = msg.to_str() else { break };
-        output.send(msg_str.to_string()).await.ok();
-        let p = peers.read().await;
-        for (id, peer) in p.iter() {
-            if !query.id.eq(id) {
-                // do not send to self
-                peer.send(Ok(Message::text(msg_str))).ok();
+        if !inbound.admit(&msg) {
+            continue;
+        }
+        if let Ok(frame) = serde_json::from_str::<ControlFrame>(msg_str) {
+            if let Some(peer) = peers.write().await.peers.get_mut(&query.id) {
+                peer.apply(frame);
             }
+            continue;
+        }
+        // a busy app loses messages rather than stalling every peer
+        if output.try_send(msg_str.to_string()).is_err() {
+            stats.count_dropped();
         }
+        // do not send to self
+        peers.write().await.broadcast(msg_str, Some(&query.id));
     }
-    peers.write().await.remove(&query.id);
+    peers.write().await.peers.remove(&query.id);
 }
@@ -0,0 +1,71 @@
+use anyhow::Context;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use qrcode::render::svg;
+use qrcode::{Color, QrCode};
+use serde::Deserialize;
+
+/// modules of light border around the code, the minimum scanners expect
+const QUIET_ZONE: usize = 4;
+const DEFAULT_SIZE: u32 = 256;
+/// larger sizes come out this wide, the png is allocated in memory at once
+const MAX_SIZE: u32 = 2048;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+/// renders `url` as a QR code at least `size` pixels wide, up to
+/// [`MAX_SIZE`], as a data url an `img` element can show directly
+pub fn data_url(url: &str, format: QrFormat, size: Option<u32>) -> anyhow::Result<String> {
+    let code = QrCode::new(url.as_bytes()).context("url does not fit into a QR code")?;
+    let size = size.unwrap_or(DEFAULT_SIZE).min(MAX_SIZE);
+    let (mime_type, bytes) = match format {
+        QrFormat::Svg => {
+            let svg = code
+                .render::<svg::Color>()
+                .min_dimensions(size, size)
+                .build();
+            ("image/svg+xml", svg.into_bytes())
+        }
+        QrFormat::Png => ("image/png", render_png(&code, size)?),
+    };
+    Ok(format!(
+        "data:{mime_type};base64,{}",
+        STANDARD.encode(bytes)
+    ))
+}
+
+/// grayscale png with whole pixels per module, so scanners see sharp edges
+fn render_png(code: &QrCode, size: u32) -> anyhow::Result<Vec<u8>> {
+    let modules = code.width();
+    let colors = code.to_colors();
+    let total = modules + 2 * QUIET_ZONE;
+    let scale = (size as usize).div_ceil(total).max(1);
+    let width = total * scale;
+
+    let mut pixels = vec![255u8; width * width];
+    for (index, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let x = (index % modules + QUIET_ZONE) * scale;
+        let y = (index / modules + QUIET_ZONE) * scale;
+        for row in y..y + scale {
+            pixels[row * width + x..row * width + x + scale].fill(0);
+        }
+    }
+
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width as u32, width as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(bytes)
+}
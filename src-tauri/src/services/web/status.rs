@@ -0,0 +1,73 @@
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime};
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+use super::WebStats;
+use crate::services::osc::{OscPlugin, OscStatus};
+use crate::services::piper_tts::{PiperInstance, PiperStatus};
+use crate::services::whisper_stt::{WhisperState, WhisperStatus};
+
+/// state of every native service, for watchdogs to notice one silently dying
+#[derive(Serialize, Clone, Debug)]
+pub struct Status {
+    /// `false` once a started service stopped on its own
+    pub healthy: bool,
+    pub whisper: WhisperStatus,
+    pub piper: PiperStatus,
+    pub osc: OscStatus,
+    pub clients: Clients,
+}
+
+/// websocket clients connected right now
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Clients {
+    pub peer: u64,
+    pub pubsub: u64,
+}
+
+pub fn collect<R: Runtime>(app: &AppHandle<R>) -> Status {
+    let whisper = app
+        .try_state::<WhisperState>()
+        .map(|state| state.status())
+        .unwrap_or_default();
+    let piper = app
+        .try_state::<PiperInstance>()
+        .map(|state| state.status())
+        .unwrap_or_default();
+    let osc = app
+        .try_state::<OscPlugin>()
+        .map(|state| state.status())
+        .unwrap_or_default();
+    let clients = app
+        .try_state::<WebStats>()
+        .map(|stats| Clients {
+            peer: stats.peer.snapshot().clients,
+            pubsub: stats.pubsub.snapshot().clients,
+        })
+        .unwrap_or_default();
+    Status {
+        healthy: !whisper.failed && piper.healthy() && osc.healthy(),
+        whisper,
+        piper,
+        osc,
+        clients,
+    }
+}
+
+/// `GET /status`, answers with 503 while unhealthy so plain HTTP checks work
+pub fn path<R: Runtime>(app: AppHandle<R>) -> BoxedFilter<(impl Reply,)> {
+    warp::path!("status")
+        .and(warp::get())
+        .map(move || {
+            let status = collect(&app);
+            let code = if status.healthy {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            warp::reply::with_status(warp::reply::json(&status), code)
+        })
+        .boxed()
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as SerdeValue;
+use tokio::sync::broadcast;
+
+/// text events native outputs may fall behind on before skipping some
+const CAPACITY: usize = 64;
+/// topic the frontend clears every text element with
+const CLEAR_TOPIC: &str = "captions.clear";
+
+/// topic prefix of the text event sources known to the frontend
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TextSource {
+    Stt,
+    #[default]
+    Textfield,
+    Translation,
+}
+
+impl TextSource {
+    pub fn topic(self) -> &'static str {
+        match self {
+            TextSource::Stt => "text.stt",
+            TextSource::Textfield => "text.textfield",
+            TextSource::Translation => "text.translation",
+        }
+    }
+
+    fn from_topic(topic: &str) -> Option<Self> {
+        [
+            TextSource::Stt,
+            TextSource::Textfield,
+            TextSource::Translation,
+        ]
+        .into_iter()
+        .find(|source| source.topic() == topic)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TextEvent {
+    Text {
+        source: TextSource,
+        /// interim results get replaced by the next text event
+        interim: bool,
+        value: String,
+    },
+    Clear,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    topic: String,
+    #[serde(default)]
+    data: SerdeValue,
+}
+
+#[derive(Deserialize)]
+struct TextData {
+    /// `TextEventType` on the frontend, `0` is final and `1` interim
+    #[serde(rename = "type")]
+    kind: u8,
+    value: String,
+}
+
+impl TextEvent {
+    /// the text event in a pubsub message, `None` for other topics
+    fn parse(message: &str) -> Option<Self> {
+        let message: Message = serde_json::from_str(message).ok()?;
+        if message.topic == CLEAR_TOPIC {
+            return Some(TextEvent::Clear);
+        }
+        let source = TextSource::from_topic(&message.topic)?;
+        let data: TextData = serde_json::from_value(message.data).ok()?;
+        Some(TextEvent::Text {
+            source,
+            interim: data.kind == 1,
+            value: data.value,
+        })
+    }
+}
+
+/// the text events the app publishes, for native outputs like caption files
+pub struct TextEvents {
+    sender: broadcast::Sender<TextEvent>,
+}
+
+impl Default for TextEvents {
+    fn default() -> Self {
+        TextEvents {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl TextEvents {
+    pub fn subscribe(&self) -> broadcast::Receiver<TextEvent> {
+        self.sender.subscribe()
+    }
+
+    /// passes on the text event in the pubsub `message`, if any
+    pub fn publish(&self, message: &str) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        if let Some(event) = TextEvent::parse(message) {
+            // only fails when the last listener went away in the meantime
+            let _ = self.sender.send(event);
+        }
+    }
+}
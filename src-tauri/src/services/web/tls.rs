@@ -0,0 +1,85 @@
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::Context;
+use itertools::Itertools;
+use local_ip_address::local_ip;
+use rcgen::CertifiedKey;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+const FINGERPRINT_FILE: &str = "fingerprint.txt";
+
+/// self signed certificate the web server uses for HTTPS
+pub struct Certificate {
+    pub cert_pem: String,
+    pub key_pem: String,
+    /// SHA-256 of the DER encoded certificate as colon separated hex, the
+    /// way browsers display it
+    pub fingerprint: String,
+}
+
+impl Certificate {
+    /// reads the certificate persisted in `directory`, generating and
+    /// persisting a new one the first time
+    ///
+    /// the certificate is kept across restarts so users only have to trust it
+    /// once
+    pub fn load_or_create(directory: &Path) -> anyhow::Result<Self> {
+        match Self::load(directory) {
+            Ok(certificate) => return Ok(certificate),
+            Err(err)
+                if err
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|err| err.kind() == ErrorKind::NotFound) => {}
+            Err(err) => return Err(err),
+        }
+
+        let certificate = Self::generate()?;
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("could not create '{}'", directory.display()))?;
+        std::fs::write(directory.join(CERT_FILE), &certificate.cert_pem)?;
+        std::fs::write(directory.join(KEY_FILE), &certificate.key_pem)?;
+        std::fs::write(directory.join(FINGERPRINT_FILE), &certificate.fingerprint)?;
+        debug!(
+            "generated web server certificate {} in '{}'",
+            certificate.fingerprint,
+            directory.display()
+        );
+        Ok(certificate)
+    }
+
+    fn load(directory: &Path) -> anyhow::Result<Self> {
+        Ok(Certificate {
+            cert_pem: std::fs::read_to_string(directory.join(CERT_FILE))?,
+            key_pem: std::fs::read_to_string(directory.join(KEY_FILE))?,
+            fingerprint: std::fs::read_to_string(directory.join(FINGERPRINT_FILE))?
+                .trim()
+                .to_string(),
+        })
+    }
+
+    /// valid for localhost and the current local address
+    fn generate() -> anyhow::Result<Self> {
+        let mut names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+        if let Ok(ip) = local_ip() {
+            names.push(ip.to_string());
+        }
+        let CertifiedKey { cert, key_pair } =
+            rcgen::generate_simple_self_signed(names).context("could not generate certificate")?;
+        Ok(Certificate {
+            cert_pem: cert.pem(),
+            key_pem: key_pair.serialize_pem(),
+            fingerprint: fingerprint(cert.der()),
+        })
+    }
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .join(":")
+}
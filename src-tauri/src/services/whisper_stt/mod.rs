@@ -1,10 +1,12 @@
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::StreamExt;
 use futures::channel::mpsc::{self};
 use futures::channel::oneshot::{self, Receiver};
+use mute::Mute;
 use ringbuf::HeapRb;
 use ringbuf::traits::{Consumer, Observer, Producer, Split};
 use rodio::DeviceTrait;
@@ -23,8 +25,12 @@ use vad::{
     audio_loop,
     get_microphone_by_name,
 };
-use whisper::{MAX_WHISPER_FRAME, Whisper, WhisperOptions, WhisperSetupError};
+use whisper::{MAX_WHISPER_FRAME, SAMPLE_RATE, Whisper, WhisperOptions, WhisperSetupError};
 
+use crate::services::metrics::Metrics;
+use crate::services::osc::OscPlugin;
+
+mod mute;
 mod vad;
 mod whisper;
 
@@ -54,6 +60,31 @@ impl Serialize for WhisperError {
 #[derive(Default)]
 pub struct WhisperState {
     stop: Mutex<Option<Receiver<()>>>,
+    mute: Mute,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct WhisperStatus {
+    pub running: bool,
+    /// transcription ended on its own instead of through [`stop`], usually
+    /// because of an error
+    pub failed: bool,
+    pub muted: bool,
+}
+
+impl WhisperState {
+    pub fn status(&self) -> WhisperStatus {
+        let mut stop = self.stop.lock().expect("should be able to lock mutex");
+        // [`start`] holds the sender for as long as transcription runs
+        let running = stop
+            .as_mut()
+            .map(|stop| matches!(stop.try_recv(), Ok(None)));
+        WhisperStatus {
+            running: running == Some(true),
+            failed: running == Some(false),
+            muted: self.mute.is_muted(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -69,9 +100,17 @@ pub struct WhisperArgs {
 
 pub fn init<R: Runtime>() -> plugin::TauriPlugin<R> {
     plugin::Builder::new("whisper-stt")
-        .invoke_handler(tauri::generate_handler![start, stop])
+        .invoke_handler(tauri::generate_handler![
+            start,
+            stop,
+            set_mute_addresses,
+            get_muted
+        ])
         .setup(|app, _api| {
-            app.manage(WhisperState::default());
+            let state = WhisperState::default();
+            let inbound = app.state::<OscPlugin>().listen();
+            tauri::async_runtime::spawn(mute::listen(app.clone(), state.mute.clone(), inbound));
+            app.manage(state);
             Ok(())
         })
         .build()
@@ -98,13 +137,16 @@ pub async fn start<R: Runtime>(app: AppHandle<R>, args: WhisperArgs) -> Result<(
     let ring = HeapRb::<i16>::try_new(MAX_WHISPER_FRAME * 2).expect("cannot allocate audio ring");
     let mut whisper = Whisper::with_options(args.model_path, whisper_opt)?;
     let (mut producer, mut consumer) = ring.split();
-    let (mut activity_tx, mut activity_rx) = mpsc::unbounded::<VadActivity>();
+    let (activity_tx, mut activity_rx) = mpsc::unbounded::<VadActivity>();
 
     let (mut err_tx, mut err_rx) = mpsc::channel(1);
     // cancellation using the condvar pattern https://doc.rust-lang.org/std/sync/struct.Condvar.html
     let cancel_pair = Arc::new((Mutex::new(false), Condvar::new()));
     let cancellation_pair = cancel_pair.clone();
 
+    let muted = state.mute.flag();
+    let metrics = app.state::<Arc<Metrics>>().inner().clone();
+    let stream_metrics = metrics.clone();
     let (device, config) =
         get_microphone_by_name(&args.input_device).map_err(WhisperError::AudioSetupError)?;
 
@@ -113,8 +155,19 @@ pub async fn start<R: Runtime>(app: AppHandle<R>, args: WhisperArgs) -> Result<(
         Some(Duration::from_millis(args.silence_interval)),
     )?;
 
+    let speaking_app = app.clone();
     // audio processing thread
     thread::spawn(move || {
+        // speech activity goes to OSC right here so avatars don't wait for the transcription
+        let osc = speaking_app.state::<OscPlugin>();
+        let mut speaking = false;
+        let mut on_activity = |activity: VadActivity| {
+            speaking = matches!(activity, VadActivity::SpeechStart);
+            osc.set_speaking(speaking);
+            // can safely drop the error case here as it only happens when the receiver has
+            // hung up (which means the stream is bound to stop soon too)
+            let _ = activity_tx.unbounded_send(activity);
+        };
         let requested_frames_pair = Arc::new((Mutex::new(vad.missing_frames()), Condvar::new()));
         let stream_requested_frames_pair = requested_frames_pair.clone();
 
@@ -130,11 +183,18 @@ pub async fn start<R: Runtime>(app: AppHandle<R>, args: WhisperArgs) -> Result<(
                         &config,
                         move |data: &[f32], _info| {
                             let _span = audio_loop.enter();
-                            let written = audio_prod.push_slice(data);
+                            let written = if muted.load(Ordering::Relaxed) {
+                                // silence instead of nothing so speech that got cut off by
+                                // muting still ends and the pipeline keeps running
+                                audio_prod.push_iter(std::iter::repeat_n(0., data.len()))
+                            } else {
+                                audio_prod.push_slice(data)
+                            };
                             let diff = data.len() - written;
 
                             if diff != 0 {
                                 warn!("cannot keep up, dropping {diff} audio frames",);
+                                stream_metrics.dropped_audio_frames.add(diff as u64);
                             }
 
                             // track how many frames we have written and notify audio thread if it
@@ -189,9 +249,12 @@ pub async fn start<R: Runtime>(app: AppHandle<R>, args: WhisperArgs) -> Result<(
             request = cvar.wait(request).unwrap();
             // handles spurious wake ups well so we don't need to check anything on the
             // condvar
-            audio_loop(&mut producer, &mut vad, &mut activity_tx);
+            audio_loop(&mut producer, &mut vad, &mut on_activity);
             *request = vad.input_frames_next();
         }
+        if speaking {
+            osc.set_speaking(false);
+        }
     });
 
     let handle_stream = async {
@@ -204,11 +267,18 @@ pub async fn start<R: Runtime>(app: AppHandle<R>, args: WhisperArgs) -> Result<(
                                 eprintln!("wasn't able to emit to frontend {}:{}", file!(), line!());
                             }
                         },
-                        Some(VadActivity::SpeechEnd(samples)) => {
+                        Some(VadActivity::SpeechEnd(samples, ended)) => {
                             if consumer.pop_slice(whisper.audio_buf(samples)) != samples {
                                 return Err(WhisperError::AudioStreamError("logic error: not enough samples could be fetched".into()));
                             }
+                            let segment = samples as f64 / SAMPLE_RATE as f64;
+                            metrics.vad_segment_seconds.observe(segment);
+                            let transcribing = Instant::now();
                             if let Some(final_text) = whisper.transcribe() {
+                                let elapsed = transcribing.elapsed();
+                                metrics.transcription_seconds.observe_duration(elapsed);
+                                metrics.whisper_real_time_factor.observe(elapsed.as_secs_f64() / segment);
+                                metrics.final_latency_seconds.observe_duration(ended.elapsed());
                                 if app.emit("whisper_stt_final", final_text).is_err() {
                                     eprintln!("wasn't able to emit to frontend {}:{}", file!(), line!());
                                 }
@@ -249,3 +319,21 @@ pub fn stop(state: State<'_, WhisperState>) {
         .expect("should be able to obtain a lock")
         .take();
 }
+
+/// sets the OSC addresses that report whether the microphone is muted,
+/// transcription pauses while any of them is truthy
+#[tauri::command]
+pub fn set_mute_addresses<R: Runtime>(
+    app: AppHandle<R>,
+    addresses: Vec<String>,
+    state: State<'_, WhisperState>,
+) {
+    if state.mute.set_addresses(addresses) {
+        mute::emit_muted(&app, false);
+    }
+}
+
+#[tauri::command]
+pub fn get_muted(state: State<'_, WhisperState>) -> bool {
+    state.mute.is_muted()
+}
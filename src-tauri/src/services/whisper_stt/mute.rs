@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use rosc::{OscMessage, OscType};
+use tauri::{AppHandle, Emitter, Runtime};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+/// VRChat reports its microphone toggle through this avatar parameter
+pub const DEFAULT_MUTE_ADDRESSES: &[&str] = &["/avatar/parameters/MuteSelf"];
+
+/// mute state as last reported over OSC
+#[derive(Clone)]
+pub struct Mute {
+    muted: Arc<AtomicBool>,
+    /// OSC addresses whose first argument carries the mute state
+    addresses: Arc<RwLock<Vec<String>>>,
+}
+
+impl Default for Mute {
+    fn default() -> Self {
+        Mute {
+            muted: Arc::default(),
+            addresses: Arc::new(RwLock::new(
+                DEFAULT_MUTE_ADDRESSES
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+            )),
+        }
+    }
+}
+
+impl Mute {
+    /// flag that can be checked from the audio callback without locking
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.muted.clone()
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    /// replaces the watched addresses and unmutes, the new addresses might
+    /// never report the old state
+    ///
+    /// # Returns
+    /// whether this unmuted
+    pub fn set_addresses(&self, addresses: Vec<String>) -> bool {
+        *self
+            .addresses
+            .write()
+            .expect("should be able to lock mute addresses") = addresses;
+        self.muted.swap(false, Ordering::Relaxed)
+    }
+
+    /// # Returns
+    /// the new mute state when `message` changed it
+    fn update(&self, message: &OscMessage) -> Option<bool> {
+        let watched = self
+            .addresses
+            .read()
+            .expect("should be able to lock mute addresses")
+            .contains(&message.addr);
+        if !watched {
+            return None;
+        }
+        let muted = match message.args.first()? {
+            OscType::Bool(v) => *v,
+            OscType::Int(v) => *v != 0,
+            OscType::Float(v) => *v != 0.,
+            _ => return None,
+        };
+        (self.muted.swap(muted, Ordering::Relaxed) != muted).then_some(muted)
+    }
+}
+
+/// follows the mute state reported by `inbound` OSC messages and emits every
+/// change as `whisper_stt_muted` event
+pub async fn listen<R: Runtime>(
+    app: AppHandle<R>,
+    mute: Mute,
+    mut inbound: broadcast::Receiver<OscMessage>,
+) {
+    loop {
+        let message = match inbound.recv().await {
+            Ok(message) => message,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("mute listener fell behind, skipped {skipped} osc messages");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if let Some(muted) = mute.update(&message) {
+            debug!("microphone muted: {muted}");
+            emit_muted(&app, muted);
+        }
+    }
+}
+
+pub fn emit_muted<R: Runtime>(app: &AppHandle<R>, muted: bool) {
+    if app.emit("whisper_stt_muted", muted).is_err() {
+        warn!("wasn't able to emit mute state to frontend");
+    }
+}
@@ -1,10 +1,9 @@
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use cpal::traits::{DeviceTrait, HostTrait};
 use cpal::{BufferSize, Device, SampleRate, StreamConfig};
 use earshot::{VoiceActivityDetector, VoiceActivityModel, VoiceActivityProfile};
-use futures::channel::mpsc::UnboundedSender;
 use ringbuf::storage::Heap;
 use ringbuf::traits::{Consumer, Observer, Producer, Split};
 use ringbuf::{CachingCons, CachingProd, SharedRb};
@@ -64,7 +63,9 @@ pub struct ResamplerSetupError(String);
 
 pub enum VadActivity {
     SpeechStart,
-    SpeechEnd(NSamples),
+    /// the samples of the segment and when the VAD ended it, so consumers can
+    /// tell how long it waited to be transcribed
+    SpeechEnd(NSamples, Instant),
 }
 
 type Cons = CachingCons<Arc<SharedRb<Heap<f32>>>>;
@@ -230,10 +231,12 @@ impl ResamplingVad {
 
 /// nop when there are not enough frames
 /// executes inner multiple times if sufficient frames are present
+///
+/// `on_activity` runs on the audio thread as soon as speech starts or ends
 pub fn audio_loop(
     ring_buffer: &mut impl Producer<Item = i16>,
     vad: &mut ResamplingVad,
-    activity: &mut UnboundedSender<VadActivity>,
+    on_activity: &mut impl FnMut(VadActivity),
 ) {
     loop {
         let status = vad.output_to(ring_buffer);
@@ -241,15 +244,11 @@ pub fn audio_loop(
             VadStatus::Silence => (),
             VadStatus::Speech => (),
             VadStatus::SpeechEnd(samples) => {
-                // can safely drop the error case here as it only happens when the receiver has
-                // hung up (which means the stream is bound to stop soon too)
-                let _ = activity.unbounded_send(VadActivity::SpeechEnd(samples));
+                on_activity(VadActivity::SpeechEnd(samples, Instant::now()));
                 continue; // make sure we run this input to completion
             }
             VadStatus::SpeechStart => {
-                // can safely drop the error case here as it only happens when the receiver has
-                // hung up (which means the stream is bound to stop soon too)
-                let _ = activity.unbounded_send(VadActivity::SpeechStart);
+                on_activity(VadActivity::SpeechStart);
                 continue; // make sure we run this input to completion
             }
         }
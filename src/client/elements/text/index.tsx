@@ -33,6 +33,7 @@ class TextController {
 
   private eventTextRef: any;
   private eventTextInputRef: any;
+  private eventClearRef: any;
   private storeEventCancelToken?: () => void;
   private sceneChangeEventCancelToken?: () => void;
 
@@ -355,6 +356,20 @@ class TextController {
     // kb input event sub
     window.ApiShared.pubsub.unsubscribe(this.eventTextInputRef);
     this.eventTextInputRef = window.ApiShared.pubsub.subscribeText(TextEventSource.textfield, e => e && this.enqueueSentence(e), true);
+
+    // clear requests, e.g. from the web server api
+    window.ApiShared.pubsub.unsubscribe(this.eventClearRef);
+    this.eventClearRef = window.ApiShared.pubsub.subscribe("captions.clear", () => this.clear());
+  }
+
+  clear() {
+    clearTimeout(this.activityTimerHandle);
+    clearTimeout(this.activityTimerDelayClearHandle);
+    if (this.boxElement.classList.contains("active") && !this.currentState.previewMode) {
+      this.boxElement.classList.remove("active");
+      this.onHide();
+    }
+    this.clearSentences();
   }
 
   bindContainer(containerElement: HTMLDivElement | null) {
@@ -396,6 +411,7 @@ class TextController {
     this.storeEventCancelToken?.();
     window.ApiShared.pubsub.unsubscribe(this.eventTextRef);
     window.ApiShared.pubsub.unsubscribe(this.eventTextInputRef);
+    window.ApiShared.pubsub.unsubscribe(this.eventClearRef);
   }
 }
 // state.previewMode
@@ -22,11 +22,23 @@ type ClientNetwork = {
   serverId: string;
   host: string;
   port: string;
+  token: string;
 }
+type NetworkInterface = {
+  name: string,
+  ip: string,
+  family: "ipv4" | "ipv6",
+  default: boolean
+}
+
 type ServerNetwork = {
   ip: string,
+  interfaces: NetworkInterface[],
   host: string,
-  port: string
+  port: string,
+  token: string,
+  httpsPort?: number,
+  tlsFingerprint?: string
 }
 
 class AppConfiguration {
@@ -64,7 +76,8 @@ class AppConfiguration {
       this.clientNetwork = {
         serverId: q.get("id") ?? "",
         host:     q.get("host") ?? location.hostname,
-        port:     q.get("port") ?? location.port
+        port:     q.get("port") ?? location.port,
+        token:    q.get("key") ?? ""
       }
     }
       // server is always app
@@ -73,8 +86,12 @@ class AppConfiguration {
       const appConfig    = await invoke<any>("plugin:web|config");
       this.serverNetwork = {
         ip:   appConfig.local_ip,
+        interfaces: appConfig.interfaces ?? [],
         host: "localhost",
-        port: appConfig.port
+        port: appConfig.port,
+        token: appConfig.token,
+        httpsPort: appConfig.https_port ?? undefined,
+        tlsFingerprint: appConfig.tls_fingerprint ?? undefined
       }
     }
   }
@@ -1,26 +1,19 @@
 import { IVRCTarget } from "../types";
-import { sendOsc }    from "./utils";
-import throttle       from "lodash/throttle";
+import { invoke }     from "@tauri-apps/api/core";
 
 class VRC_TextboxTarget implements IVRCTarget {
+  // paging, the 144 char limit and rate limiting are handled by the osc plugin
   pushFinal(value: string): void {
-    sendOsc("/chatbox/input", [
-      { String: value }, // value
-      { Bool: true }, // is final
-    ]);
+    invoke<void>("plugin:osc|chatbox_submit", { text: value });
   }
 
   get state() {
     return window.ApiServer.state.services.vrc.data.textbox;
   }
 
-  #sendIndicatorThrottled = throttle(this.#sendIndicator, 1400);
-  #sendIndicator(value: boolean) {
-    sendOsc("/chatbox/typing", [{ Bool: value }]);
-  }
   pushInterim(_value: string): void {
     if (this.state.indicator)
-      this.#sendIndicatorThrottled(true);
+      invoke<void>("plugin:osc|chatbox_typing", { typing: true });
   }
   cancel(): void {}
 }
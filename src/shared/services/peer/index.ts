@@ -12,7 +12,7 @@ class Service_Peer implements IServiceInterface {
 
   getClientLink(): string {
     const n = window.Config.serverNetwork;
-    return `${n.host}:${n.port}/client`;
+    return `${n.host}:${n.port}/client?key=${n.token}`;
   }
 
   copyClientLink() {
@@ -32,6 +32,7 @@ class Service_Peer implements IServiceInterface {
       id: "server",
       host: window.Config.serverNetwork.host,
       port: window.Config.serverNetwork.port,
+      token: window.Config.serverNetwork.token,
     });
   }
   private onConfigReceived?: (data: any) => any;
@@ -52,6 +53,7 @@ class Service_Peer implements IServiceInterface {
       id:   "server",
       host: window.Config.clientNetwork.host,
       port: window.Config.clientNetwork.port,
+      token: window.Config.clientNetwork.token,
     });
     // wait for runtime config
     return new Promise<AppConfiguration["clientInitialState"]>((res) => this.onConfigReceived = res);
@@ -5,6 +5,9 @@ import {messageYjsSyncStep1, messageYjsSyncStep2, readSyncMessage, readUpdate, w
 import {nanoid}      from "nanoid";
 import { BaseEvent } from "@/types";
 
+// pages served over https may only open secure sockets
+const isSecurePage = () => location.protocol === "https:";
+
 export class PeerjsProvider extends EventTarget {
   constructor(
     private document: Doc,
@@ -16,16 +19,17 @@ export class PeerjsProvider extends EventTarget {
   connectServer(params: {
     id: string,
     host: string,
-    port: string
+    port: string,
+    token: string
   }) {
     // track document update
     this.document.on("update", update => this.broadcastUpdate(this.serializeUpdate(update)));
     this.#peer = new Peer(params.id, {
       host: params.host,
       port:   parseInt(params.port),
-      key:    '',
+      key:    params.token, // the server expects its access token as peerjs key
       path:   'peer',
-      secure: false,
+      secure: isSecurePage(),
       debug: 0});
     this.#peer.on("open", () => {});
     this.#peer.on("connection", clientConn => {
@@ -52,15 +56,16 @@ export class PeerjsProvider extends EventTarget {
   async connectClient(params: {
     id: string,
     host: string,
-    port: string
+    port: string,
+    token: string
   }) {
     await new Promise((resolve, reject) => {
       this.#peer = new Peer(nanoid(64), {
         host: params.host,
         port:   parseInt(params.port),
-        key:    '',
+        key:    params.token, // the server expects its access token as peerjs key
         path:   'peer',
-        secure: false,
+        secure: isSecurePage(),
         debug: 0
       });
       if (!this.#peer)
@@ -9,6 +9,13 @@ import { proxyMap } from "valtio/utils";
 
 // todo move event to zod
 
+/** requests of the REST api of the web server, see `web/api.rs` */
+type WebApiAction =
+  | { action: "tts", value: string }
+  | { action: "clear_captions" }
+  | { action: "stt_start" }
+  | { action: "stt_stop" };
+
 type RegisteredEvent = {
   label: string;
   description?: string;
@@ -18,6 +25,9 @@ type RegisteredEvent = {
 class Service_PubSub implements IServiceInterface {
   constructor() { }
   #socket?: WebSocket;
+  /** sequence number of the last message received over the link */
+  #linkSeq?: number;
+  #linkAddress?: string;
   serviceState = proxy({
     state: ServiceNetworkState.disconnected,
   });
@@ -37,7 +47,10 @@ class Service_PubSub implements IServiceInterface {
     if (!window.Config.isServer())
       return;
     if (typeof stringEvent === "string") try {
-      const { topic, data }: BaseEvent = JSON.parse(stringEvent);
+      const { topic, data, seq }: BaseEvent & { seq?: number } = JSON.parse(stringEvent);
+      // remember where to pick up after reconnecting a link
+      if (typeof seq === "number")
+        this.#linkSeq = seq;
       if (typeof data !== "object")
         return;
       const validated = TextEventSchema.safeParse(data);
@@ -59,6 +72,23 @@ class Service_PubSub implements IServiceInterface {
     }
   }
 
+  private consumeWebApiAction(action: WebApiAction) {
+    switch (action.action) {
+      case "tts":
+        window.ApiServer.tts.play(action.value);
+        break;
+      case "clear_captions":
+        this.publish("captions.clear", {});
+        break;
+      case "stt_start":
+        window.ApiServer.stt.start();
+        break;
+      case "stt_stop":
+        window.ApiServer.stt.stop();
+        break;
+    }
+  }
+
   public registeredEvents = proxyMap<string, RegisteredEvent>([]);
 
   registerEvent = (event: RegisteredEvent) => this.registeredEvents.set(event.value, event);
@@ -68,6 +98,9 @@ class Service_PubSub implements IServiceInterface {
     window.Config.isServer() && listen('pubsub', (event) => {
       this.consumePubSubMessage(event.payload as string);
     });
+    window.Config.isServer() && listen<WebApiAction>('web_api', (event) => {
+      this.consumeWebApiAction(event.payload);
+    });
 
     this.registerEvent({ label: "Speech to text", value: TextEventSource.stt });
     this.registerEvent({ label: "Translation", value: TextEventSource.translation });
@@ -149,26 +182,33 @@ class Service_PubSub implements IServiceInterface {
 
   copyLinkAddress() {
     const conf = window.Config.serverNetwork;
-    navigator.clipboard.writeText(`${conf.ip}:${conf.port}`)
+    navigator.clipboard.writeText(`${conf.ip}:${conf.port}?key=${conf.token}`)
     toast.success("Copied!");
   }
 
   linkConnect() {
-    const ipValidator = /^(([0-9]|[1-9][0-9]|1[0-9]{2}|2[0-4][0-9]|25[0-5])\.){3}([0-9]|[1-9][0-9]|1[0-9]{2}|2[0-4][0-9]|25[0-5]):[0-9]+$/;
+    const ipValidator = /^(([0-9]|[1-9][0-9]|1[0-9]{2}|2[0-4][0-9]|25[0-5])\.){3}([0-9]|[1-9][0-9]|1[0-9]{2}|2[0-4][0-9]|25[0-5]):[0-9]+(\?key=[A-Za-z0-9]+)?$/;
     const fullAddress = window.ApiServer.state.linkAddress;
     if (!fullAddress.match(ipValidator))
       return;
+    // the access token of the other instance is optional in the address
+    const [address, key = ""] = fullAddress.split("?key=");
 
     const conf = window.Config.serverNetwork;
     // prevent loop connect
-    if (`${conf.ip}:${conf.port}` === fullAddress) {
+    if (`${conf.ip}:${conf.port}` === address) {
       toast.error("Cannot connect to self");
       return;
     }
 
 
     this.serviceState.state = ServiceNetworkState.connecting;
-    this.#socket = new WebSocket(`ws://${fullAddress}/pubsub?id=${window.ApiServer.state.id}-${Date.now()}`);
+    // replay what was missed while disconnected
+    if (this.#linkAddress !== address)
+      this.#linkSeq = undefined;
+    this.#linkAddress = address;
+    const replay = this.#linkSeq === undefined ? "" : `&since=${this.#linkSeq}`;
+    this.#socket = new WebSocket(`ws://${address}/pubsub?id=${window.ApiServer.state.id}-${Date.now()}&key=${key}${replay}`);
     const a = setTimeout(() => {
       this.#socket?.close();
     }, 5000);